@@ -0,0 +1,19 @@
+use telebot::Bot;
+use std::env;
+
+// import all available functions
+use telebot::functions::*;
+
+// Sends a single message without an async runtime, e.g. from a cron job
+//
+// TELEGRAM_BOT_KEY=... CHAT_ID=... cargo run --example blocking -- "Backup finished"
+fn main() {
+    let bot = Bot::new(&env::var("TELEGRAM_BOT_KEY").unwrap());
+    let chat_id = env::var("CHAT_ID").unwrap().parse().unwrap();
+    let text = env::args().nth(1).unwrap_or_else(|| "Hello from a script".into());
+
+    match bot.request.message(chat_id, text).send_blocking() {
+        Ok(msg) => println!("Sent message {}", msg.message_id),
+        Err(err) => eprintln!("Failed to send the message: {}", err),
+    }
+}
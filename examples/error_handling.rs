@@ -1,97 +1,93 @@
 use telebot::{Bot, File};
+use telebot::bot::RequestHandle;
+use telebot::objects::Message;
 use failure::Error;
-use futures::stream::Stream;
-use futures::Future;
+use futures::{future, TryStreamExt};
 use std::env;
 
 // import all available functions
 use telebot::functions::*;
 
-fn main() {
-    // Create the bot
-    let mut bot = Bot::new(&env::var("TELEGRAM_BOT_KEY").unwrap()).update_interval(200);
+// Register a location command which will send a location to requests like /location 2.321 12.32
+enum LocationErr {
+    Telegram(Error),
+    WrongLocationFormat,
+}
+
+async fn send_location(bot: RequestHandle, mut msg: Message) -> Result<(), Error> {
+    let parsed = msg.text.take().and_then(|pos| {
+        let mut elms = pos.split_whitespace().take(2).filter_map(
+            |x| x.parse::<f32>().ok(),
+        );
+
+        match (elms.next(), elms.next()) {
+            (Some(a), Some(l)) => Some((a, l)),
+            _ => None,
+        }
+    });
+
+    let result = match parsed {
+        Some((long, alt)) => bot.location(msg.chat.id, long, alt).send().await
+            .map(|_| ())
+            .map_err(LocationErr::Telegram),
+        None => Err(LocationErr::WrongLocationFormat),
+    };
 
-    // Register a location command which will send a location to requests like /location 2.321 12.32
-    enum LocationErr {
-        Telegram(Error),
-        WrongLocationFormat,
+    if let Err(err) = result {
+        let text = match err {
+            LocationErr::Telegram(err) => format!("Telegram error: {:?}", err),
+            LocationErr::WrongLocationFormat => "Couldn't parse the location!".into(),
+        };
+
+        bot.message(msg.chat.id, text).send().await?;
     }
 
-    let handle = bot.new_cmd("/location")
-        .then(|result| {
-            let (bot, mut msg) = result.expect("Strange telegram error!");
-
-            if let Some(pos) = msg.text.take() {
-                let mut elms = pos.split_whitespace().take(2).filter_map(
-                    |x| x.parse::<f32>().ok(),
-                );
-
-                if let (Some(a), Some(l)) = (elms.next(), elms.next()) {
-                    return Ok((bot, msg, a, l));
-                }
-            }
-
-            return Err((bot, msg, LocationErr::WrongLocationFormat));
-        })
-        .and_then(|(bot, msg, long, alt)| {
-            bot.location(msg.chat.id, long, alt).send().map_err(|err| {
-                (bot, msg, LocationErr::Telegram(err))
-            })
-        })
-        .or_else(|(bot, msg, err)| {
-            let text = {
-                match err {
-                    LocationErr::Telegram(err) => format!("Telegram error: {:?}", err),
-                    LocationErr::WrongLocationFormat => "Couldn't parse the location!".into(),
-                }
-            };
-
-            bot.message(msg.chat.id, text).send()
-        })
-        .for_each(|_| Ok(()));
-
-    // Register a get_my_photo command which will send the own profile photo to the chat
-    enum PhotoErr {
-        Telegram(Error),
-        NoPhoto,
+    Ok(())
+}
+
+// Register a get_my_photo command which will send the own profile photo to the chat
+enum PhotoErr {
+    Telegram(Error),
+    NoPhoto,
+}
+
+async fn send_my_photo(bot: RequestHandle, msg: Message) -> Result<(), Error> {
+    let user_id = msg.from.clone().unwrap().id;
+
+    let result = match bot.get_user_profile_photos(user_id).limit(1u32).send().await {
+        Ok((_, photos)) if photos.total_count == 0 => Err(PhotoErr::NoPhoto),
+        Ok((bot, photos)) => bot.photo(msg.chat.id)
+            .file(File::Telegram(photos.photos[0][0].clone().file_id))
+            .send()
+            .await
+            .map(|_| ())
+            .map_err(PhotoErr::Telegram),
+        Err(err) => Err(PhotoErr::Telegram(err)),
+    };
+
+    if let Err(err) = result {
+        let text = match err {
+            PhotoErr::Telegram(err) => format!("Telegram Error: {:?}", err),
+            PhotoErr::NoPhoto => "No photo exists!".into(),
+        };
+
+        bot.message(msg.chat.id, text).send().await?;
     }
 
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() {
+    // Create the bot
+    let mut bot = Bot::new(&env::var("TELEGRAM_BOT_KEY").unwrap()).update_interval(200);
+
+    let handle = bot.new_cmd("/location")
+        .try_for_each(|(bot, msg)| send_location(bot, msg));
+
     let handle2 = bot.new_cmd("/get_my_photo")
-        .then(|result| {
-            let (bot, msg) = result.expect("Strange telegram error!");
-
-            let user_id = msg.from.clone().unwrap().id;
-
-            bot.get_user_profile_photos(user_id)
-                .limit(1u32)
-                .send()
-                .then(|result| match result {
-                    Ok((bot, photos)) => {
-                        if photos.total_count == 0 {
-                            return Err((bot, msg, PhotoErr::NoPhoto));
-                        }
-
-                        return Ok((bot, msg, photos.photos[0][0].clone().file_id));
-                    }
-                    Err(err) => Err((bot, msg, PhotoErr::Telegram(err))),
-                })
-        })
-        .and_then(|(bot, msg, file_id)| {
-            bot.photo(msg.chat.id)
-                .file(File::Telegram(file_id))
-                .send()
-                .map_err(|err| (bot, msg, PhotoErr::Telegram(err)))
-        })
-        .or_else(|(bot, msg, err)| {
-            let text = match err {
-                PhotoErr::Telegram(err) => format!("Telegram Error: {:?}", err),
-                PhotoErr::NoPhoto => "No photo exists!".into(),
-            };
-
-            bot.message(msg.chat.id, text).send()
-        })
-        .for_each(|_| Ok(()));
+        .try_for_each(|(bot, msg)| send_my_photo(bot, msg));
 
     // enter the main loop
-    bot.run_with(handle.join(handle2));
+    bot.run_with(future::try_join(handle, handle2)).await;
 }
@@ -1,5 +1,5 @@
 use telebot::Bot;
-use futures::stream::Stream;
+use futures::{future, TryStreamExt};
 use std::env;
 
 use erased_serde::Serialize;
@@ -7,7 +7,8 @@ use erased_serde::Serialize;
 use telebot::functions::*;
 use telebot::objects::*;
 
-fn main() {
+#[tokio::main]
+async fn main() {
     // Create the bot
     let mut bot = Bot::new(&env::var("TELEGRAM_BOT_KEY").unwrap()).update_interval(200);
 
@@ -31,11 +32,8 @@ fn main() {
                 .is_personal(true)
                 .send()
         })
-        .for_each(|_| Ok(()));
+        .try_for_each(|_| future::ok(()));
 
     // enter the main loop
-    bot.run_with(stream);
-    //tokio::spawn(stream.into_future().map_err(|_| ()));
-
-    //lp.run(stream.for_each(|_| Ok(())).into_future()).unwrap();
+    bot.run_with(stream).await;
 }
@@ -1,25 +1,23 @@
 use telebot::Bot;
-use futures::stream::Stream;
+use futures::{future, TryStreamExt};
 use std::env;
-use futures::{IntoFuture, Future};
 
-fn main() {
+#[tokio::main]
+async fn main() {
     // Create the bot
     let bot = Bot::new(&env::var("TELEGRAM_BOT_KEY").unwrap()).update_interval(200);
 
-    let stream = bot.get_stream(None).for_each(|(_, msg)| {
+    let stream = bot.get_stream(None).try_for_each(|(_, msg)| {
         println!("Received: {:#?}", msg);
 
-        Ok(())
+        future::ok(())
     });
 
     // enter the main loop
-    tokio::run(stream.into_future().map_err(|_| ()));
-    /*let res = lp.run(stream.for_each(|_| Ok(())).into_future());
-    if let Err(err) = res {
+    if let Err(err) = stream.await {
         eprintln!("Event loop shutdown:");
         for (i, cause) in err.iter_causes().enumerate() {
             eprintln!(" => {}: {}", i, cause);
         }
-    }*/
+    }
 }
@@ -1,11 +1,12 @@
-use telebot::{Bot, file::File};
-use futures::stream::Stream;
+use telebot::{Bot, file::{File, InputMedia}};
+use futures::{future, TryStreamExt};
 use std::env;
 
 // import all available functions
 use telebot::functions::*;
 
-fn main() {
+#[tokio::main]
+async fn main() {
     // Create the bot
     let mut bot = Bot::new(&env::var("TELEGRAM_BOT_KEY").unwrap()).update_interval(200);
 
@@ -14,11 +15,11 @@ fn main() {
             bot.mediagroup(msg.chat.id)
                 .file(File::Url("https://upload.wikimedia.org/wikipedia/commons/f/f4/Honeycrisp.jpg".into()))
                 .file(File::Url("https://upload.wikimedia.org/wikipedia/en/3/3e/Pooh_Shepard1928.jpg".into()))
-                .file("examples/bee.jpg")
+                .file(InputMedia::photo("examples/bee.jpg").caption("A bee"))
                 .send()
         })
-        .for_each(|_| Ok(()));
+        .try_for_each(|_| future::ok(()));
 
     // enter the main loop
-    bot.run_with(handle);
+    bot.run_with(handle).await;
 }
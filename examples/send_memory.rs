@@ -1,11 +1,12 @@
 use telebot::Bot;
-use futures::stream::Stream;
+use futures::{future, TryStreamExt};
 use std::env;
 
 // import all available functions
 use telebot::functions::*;
 
-fn main() {
+#[tokio::main]
+async fn main() {
     // Create the bot
     let mut bot = Bot::new(&env::var("TELEGRAM_BOT_KEY").unwrap()).update_interval(200);
 
@@ -50,8 +51,8 @@ Scene, Melpomene, mankind.
                 .caption("The Chaos")
                 .send()
         })
-        .for_each(|_| Ok(()));
+        .try_for_each(|_| future::ok(()));
 
     // enter the main loop
-    bot.run_with(handle);
+    bot.run_with(handle).await;
 }
@@ -1,11 +1,12 @@
 use telebot::Bot;
-use futures::stream::Stream;
+use futures::{future, TryStreamExt};
 use std::env;
 
 // import all available functions
 use telebot::functions::*;
 
-fn main() {
+#[tokio::main]
+async fn main() {
     // Create the bot
     let mut bot = Bot::new(&env::var("TELEGRAM_BOT_KEY").unwrap()).update_interval(200);
 
@@ -15,8 +16,8 @@ fn main() {
                 .file("examples/send_self.rs")
                 .send()
         })
-        .for_each(|_| Ok(()));
+        .try_for_each(|_| future::ok(()));
 
     // enter the main loop
-    bot.run_with(handle);
+    bot.run_with(handle).await;
 }
@@ -0,0 +1,30 @@
+use telebot::Bot;
+use futures::{future, TryStreamExt};
+use std::{env, time::Duration};
+
+// import all available functions
+use telebot::functions::*;
+
+#[tokio::main]
+async fn main() {
+    // Create the bot, handlers get five seconds to finish after Ctrl-C
+    let mut bot = Bot::new(&env::var("TELEGRAM_BOT_KEY").unwrap())
+        .update_interval(200)
+        .shutdown_timeout(Duration::from_secs(5));
+
+    let handle = bot.new_cmd("/reply")
+        .and_then(|(bot, msg)| bot.message(msg.chat.id, msg.text.unwrap_or_default()).send())
+        .try_for_each(|_| future::ok(()));
+
+    // Stop polling on Ctrl-C, the offset of all handled updates is confirmed with Telegram
+    let shutdown = bot.shutdown_handle();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            shutdown.shutdown();
+        }
+    });
+
+    bot.run_with(handle).await;
+
+    println!("Bot stopped");
+}
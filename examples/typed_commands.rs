@@ -0,0 +1,40 @@
+use telebot::Bot;
+use telebot::command::{BotCommands, Rest};
+use futures::{future, TryStreamExt};
+use std::env;
+
+// import all available functions
+use telebot::functions::*;
+
+#[derive(BotCommands)]
+enum Command {
+    #[description = "Sends a map of the location"]
+    Location { lat: f32, long: f32 },
+    #[command = "say"]
+    #[description = "Repeats the text"]
+    Echo(Rest),
+}
+
+#[tokio::main]
+async fn main() {
+    // Create the bot
+    let mut bot = Bot::new(&env::var("TELEGRAM_BOT_KEY").unwrap()).update_interval(200);
+
+    // Every command of the enum is parsed before it reaches the stream, /help and the command
+    // menu of the Telegram client are generated from the descriptions
+    let handle = bot.commands::<Command>()
+        .and_then(|(bot, msg, cmd)| async move {
+            match cmd {
+                Ok(Command::Location { lat, long }) => bot.location(msg.chat.id, lat, long).send().await?,
+                Ok(Command::Echo(text)) => bot.message(msg.chat.id, text.0).send().await?,
+                // tell the user what went wrong
+                Err(err) => bot.message(msg.chat.id, err.to_string()).send().await?,
+            };
+
+            Ok(())
+        })
+        .try_for_each(|_| future::ok(()));
+
+    // Enter the main loop
+    bot.run_with(handle).await;
+}
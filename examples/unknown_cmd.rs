@@ -1,23 +1,24 @@
 use telebot::Bot;
-use futures::{Future, stream::Stream};
+use futures::{future, TryStreamExt};
 use std::env;
 
 // import all available functions
 use telebot::functions::*;
 
-fn main() {
+#[tokio::main]
+async fn main() {
     // Create the bot
     let mut bot = Bot::new(&env::var("TELEGRAM_BOT_KEY").unwrap()).update_interval(200);
 
     let known = bot.new_cmd("/known")
         .and_then(|(bot, msg)| bot.message(msg.chat.id, "This one is known".into()).send())
-        .for_each(|_| Ok(()));
+        .try_for_each(|_| future::ok(()));
 
     // Every possible command is unknown
     let unknown = bot.unknown_cmd()
         .and_then(|(bot, msg)| bot.message(msg.chat.id, "Unknown command".into()).send())
-        .for_each(|_| Ok(()));
+        .try_for_each(|_| future::ok(()));
 
     // Enter the main loop
-    bot.run_with(known.join(unknown));
+    bot.run_with(future::try_join(known, unknown)).await;
 }
@@ -0,0 +1,34 @@
+use telebot::{Bot, webhook::Webhook};
+use futures::{future, TryStreamExt};
+use std::env;
+
+// import all available functions
+use telebot::functions::*;
+
+// Updates can be simulated locally by posting them to the listener:
+//
+// curl -X POST -H "Content-Type: application/json" \
+//      -H "X-Telegram-Bot-Api-Secret-Token: secret" \
+//      -d @update.json http://127.0.0.1:8443/telegram
+#[tokio::main]
+async fn main() {
+    let webhook = Webhook::new("127.0.0.1:8443".parse().unwrap(), "/telegram")
+        .secret_token("secret");
+
+    // Create the bot, it receives updates from the reverse proxy instead of polling
+    let mut bot = Bot::new(&env::var("TELEGRAM_BOT_KEY").unwrap()).webhook(webhook);
+
+    // Register a reply command which answers a message
+    let handle = bot.new_cmd("/reply")
+        .and_then(|(bot, msg)| {
+            let mut text = msg.text.unwrap().clone();
+            if text.is_empty() {
+                text = "<empty>".into();
+            }
+
+            bot.message(msg.chat.id, text).send()
+        })
+        .try_for_each(|_| future::ok(()));
+
+    bot.run_with(handle).await;
+}
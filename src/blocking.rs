@@ -0,0 +1,43 @@
+//! A blocking client for scripts and services without an async runtime
+//!
+//! Every function wrapper has a `send_blocking` method besides `send`. It sends the request on a
+//! small runtime owned by this module and blocks the current thread until the answer arrives:
+//!
+//! ```
+//! use telebot::Bot;
+//! use telebot::functions::*;
+//! use telebot::transport::MockTransport;
+//! use serde_json::json;
+//!
+//! let mock = MockTransport::new();
+//! mock.answer("getMe", json!({"id": 1, "first_name": "Bot", "username": "test_bot"}));
+//!
+//! let bot = Bot::new("TOKEN").transport(mock);
+//! let me = bot.request.get_me().send_blocking().unwrap();
+//!
+//! assert_eq!(me.username, Some("test_bot".into()));
+//! ```
+//!
+//! The blocking methods must not be called from within an async context, use `send` there.
+
+use std::sync::OnceLock;
+
+use futures::Future;
+use tokio::runtime::{Builder, Runtime};
+
+static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+
+/// Runs a future to completion on the runtime of the blocking client
+///
+/// The runtime is created on the first call and shared by all threads, so that idle connections
+/// of the hyper client are reused between requests.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let runtime = RUNTIME.get_or_init(|| {
+        Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("Failed to create the runtime of the blocking client")
+    });
+
+    runtime.block_on(future)
+}
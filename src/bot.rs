@@ -4,197 +4,529 @@ use crate::objects;
 //use functions::FunctionGetMe;
 use crate::error::{ErrorKind, TelegramError};
 use crate::file::File;
+use crate::webhook::Webhook;
+use crate::transport::{Transport, HyperTransport};
+use crate::retry::{RetryPolicy, ReplayableFiles};
+use crate::ratelimit::RateLimiter;
+use crate::migration::{ChatMigration, MigrationNotifier};
+use crate::download::{self, FileStream};
+use crate::shutdown::ShutdownHandle;
+use crate::offset::{OffsetStore, UpdateGuard};
+use crate::filter::MessageFilter;
+use crate::router::Route;
+use crate::command::{self, BotCommands, CommandError, CommandInfo, CommandScope, CommandSink};
+use crate::dialogue::{self, Dialogue, DialogueRouter, DialogueUpdate};
+use crate::session::{Session, SessionKey, SessionScope, SessionStore, Sessions};
+use crate::middleware::{Annotations, Flow, Middleware, RequestContext, UpdateContext};
+use crate::guard::{Deliver, Guard, GuardJob, MemberCache};
+use crate::queue::{self, ChatTurn, HandlerSender, Lanes, Processing, Queues};
 
-use std::{str, time::{Duration, Instant}, collections::HashMap, sync::Arc};
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::{str, any::Any, time::{Duration, Instant}, collections::HashMap, path::Path, sync::Arc};
 
-use tokio::timer::Interval;
-use hyper::{Body as Body2, Client, Request, Uri, header::CONTENT_TYPE, client::{HttpConnector, ResponseFuture}, rt::Stream};
-use hyper_tls::HttpsConnector;
-use hyper_multipart::client::multipart;
+use tokio::{io::AsyncWrite, time};
 use serde_json::{self, value::Value};
-use futures::{stream, Future, future::IntoFuture, sync::mpsc::{self, UnboundedSender}};
+use futures::{stream, Future, FutureExt, Stream, StreamExt, TryFutureExt, TryStreamExt, future::{self, BoxFuture, Either}, channel::mpsc::UnboundedReceiver};
 use failure::{Error, Fail, ResultExt};
-use hyper_multipart_rfc7578::client::multipart::Body;
+use futures_retry::FutureRetry;
+
+/// The URL of the official Bot API server
+pub const DEFAULT_API_URL: &str = "https://api.telegram.org";
+
+/// The number of guarded commands whose permission is checked at the same time
+const GUARD_CHECKS: usize = 16;
 
 /// A clonable request handle struct
 /// Allows the construction of requests to the Telegram server
 #[derive(Clone)]
 pub struct RequestHandle {
     key: String,
-    pub inner: Arc<hyper::Client<HttpsConnector<HttpConnector>, Body2>>
+    base_url: String,
+    retry: Option<RetryPolicy>,
+    limiter: Option<RateLimiter>,
+    follow_migrations: bool,
+    migrations: Option<MigrationNotifier>,
+    session: Option<Session>,
+    middlewares: Vec<Arc<dyn Middleware>>,
+    annotations: Annotations,
+    turn: Option<Arc<ChatTurn>>,
+    pub inner: Arc<dyn Transport>
 }
 
 impl RequestHandle {
+    /// Creates a new request handle which sends requests with the transport to the official
+    /// Telegram server
+    pub fn new(key: &str, transport: Arc<dyn Transport>) -> RequestHandle {
+        RequestHandle {
+            key: key.into(),
+            base_url: DEFAULT_API_URL.into(),
+            retry: None,
+            limiter: None,
+            follow_migrations: false,
+            migrations: None,
+            session: None,
+            middlewares: Vec::new(),
+            annotations: Annotations::default(),
+            turn: None,
+            inner: transport
+        }
+    }
+
+    /// Adds a middleware which sees every request and its answer
+    pub fn middleware(mut self, middleware: Arc<dyn Middleware>) -> RequestHandle {
+        self.middlewares.push(middleware);
+
+        self
+    }
+
+    /// Returns the annotation of this type, which a middleware attached to the update
+    pub fn annotation<T: Any + Send + Sync>(&self) -> Option<&T> {
+        self.annotations.get()
+    }
+
+    /// Passes a request through the middlewares, fails if one of them stops it
+    fn before_request(&self, func: &'static str, body: &mut Value) -> Result<(), Error> {
+        for middleware in &self.middlewares {
+            if middleware.on_request(&mut RequestContext { function: func, body }) == Flow::Stop {
+                return Err(Error::from(ErrorKind::Middleware));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Passes the answer of a request through the middlewares in reverse order
+    fn after_request(&self, func: &'static str, result: &Result<String, Error>) {
+        for middleware in self.middlewares.iter().rev() {
+            middleware.on_response(func, result);
+        }
+    }
+
+    /// Returns the session of the chat or user who sent the update, if a session store is set
+    ///
+    /// The session is saved when the last clone of this handle is dropped.
+    pub fn session(&self) -> Option<&Session> {
+        self.session.as_ref()
+    }
+
+    /// Returns the URL of a Telegram function
+    pub fn function_url(&self, func: &str) -> String {
+        format!("{}/bot{}/{}", self.base_url, self.key, func)
+    }
+
+    /// Returns the download URL of a file, the path is taken from the answer of getFile
+    pub fn file_url(&self, file_path: &str) -> String {
+        format!("{}/file/bot{}/{}", self.base_url, self.key, file_path)
+    }
+
+    /// Repeats requests which failed because of flood control or network errors
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> RequestHandle {
+        self.retry = Some(policy);
+
+        self
+    }
+
+    /// Delays messages to stay within the rate limits of Telegram
+    pub fn rate_limiter(mut self, limiter: RateLimiter) -> RequestHandle {
+        self.limiter = Some(limiter);
+
+        self
+    }
+
+    /// Resends requests to a group which has been upgraded to a supergroup to the new chat
+    pub fn follow_migrations(mut self, follow: bool) -> RequestHandle {
+        self.follow_migrations = follow;
+
+        self
+    }
+
+    /// Reports a migration to the stream returned by `Bot::migrations`, if there is one
+    pub async fn notify_migration(&self, migration: ChatMigration) {
+        if let Some(ref notifier) = self.migrations {
+            notifier.notify(self, migration).await;
+        }
+    }
+
     /// Creates a new request and adds a JSON message to it. The returned Future contains a the
     /// reply as a string.  This method should be used if no file is added becontext a JSON msg is
     /// always compacter than a formdata one.
-    pub fn fetch_json(
+    pub async fn fetch_json(
         &self,
         func: &'static str,
         msg: &str,
-    ) -> impl Future<Item = String, Error = Error> {
+    ) -> Result<String, Error> {
+        if self.middlewares.is_empty() {
+            return self.deliver_json(func, msg).await;
+        }
+
+        let mut body = serde_json::from_str::<Value>(msg).context(ErrorKind::JsonParse)?;
+        self.before_request(func, &mut body)?;
 
+        let result = self.deliver_json(func, &body.to_string()).await;
+        self.after_request(func, &result);
+
+        result
+    }
+
+    /// Sends a JSON message and repeats it after a migration
+    async fn deliver_json(
+        &self,
+        func: &'static str,
+        msg: &str,
+    ) -> Result<String, Error> {
         debug!("Send JSON {}: {}", func, msg);
-        let request = self.build_json(func, String::from(msg)).unwrap();
 
-        _fetch(self.inner.request(request))
+        let err = match self.send_json(func, msg.into()).await {
+            Ok(answer) => return Ok(answer),
+            Err(err) => err
+        };
+
+        let migrated = serde_json::from_str::<Value>(msg).ok()
+            .and_then(|msg| self.migrate(func, &err, msg));
+
+        match migrated {
+            Some((msg, migration)) => {
+                self.notify_migration(migration).await;
+
+                self.send_json(func, msg.to_string()).await
+            }
+            None => Err(err)
+        }
     }
 
-    /// Builds the HTTP header for a JSON request. The JSON is already converted to a str and is
-    /// appended to the POST header.
-    fn build_json(
+    /// Sends a JSON message with the rate limiter and retry policy of this handle
+    async fn send_json(
         &self,
         func: &'static str,
         msg: String,
-    ) -> Result<Request<Body2>, Error> {
-        let url: Result<Uri, _> =
-            format!("https://api.telegram.org/bot{}/{}", self.key, func).parse();
+    ) -> Result<String, Error> {
+        let inner = self.inner.clone();
+        let url = self.function_url(func);
+        let limiter = self.limiter.clone();
+        let value = limiter.as_ref().and_then(|_| serde_json::from_str::<Value>(&msg).ok());
 
-        debug!("Send message {}", msg);
+        let send = move || {
+            let (inner, url, msg) = (inner.clone(), url.clone(), msg.clone());
+            let (limiter, value) = (limiter.clone(), value.clone());
 
-        let req = Request::post(url.context(ErrorKind::Uri)?)
-            .header(CONTENT_TYPE, "application/json")
-            .body(msg.into())
-            .context(ErrorKind::Hyper)?;
+            async move {
+                throttle(&limiter, func, value.as_ref()).await;
 
-        Ok(req)
+                let answer = inner.fetch_json(url, func, msg).await?;
+
+                parse_answer(&answer)
+            }
+        };
+
+        match self.retry {
+            Some(ref policy) => FutureRetry::new(send, policy.handler(func)).await
+                .map(|(answer, _)| answer)
+                .map_err(|(err, _)| err),
+            None => send().await
+        }
     }
-    ///
+
+    /// Resolves the path of a file with getFile and returns its content as a stream of chunks
+    pub async fn download_file(&self, file_id: String) -> Result<(objects::File, FileStream), Error> {
+        use crate::functions::FunctionGetFile;
+
+        let (handle, file) = self.get_file(file_id).send().await?;
+        let content = handle.download(&file)?;
+
+        Ok((file, content))
+    }
+
+    /// Downloads a file which was already resolved with getFile. Absolute paths are returned by a
+    /// Bot API server in local mode and are read from the disk.
+    pub fn download(&self, file: &objects::File) -> Result<FileStream, Error> {
+        let path = file.file_path.as_ref().ok_or(ErrorKind::NoFilePath)?;
+
+        let content = if Path::new(path).is_absolute() {
+            download::read_local(path.into())
+        } else {
+            self.inner.download(self.file_url(path))
+        };
+
+        Ok(download::check_size(content, file.file_size))
+    }
+
+    /// Downloads a file and writes its content to `writer`
+    pub async fn download_file_to<W>(&self, file_id: String, writer: W) -> Result<(objects::File, W), Error>
+    where
+        W: AsyncWrite + Unpin + Send,
+    {
+        let (file, content) = self.download_file(file_id).await?;
+        let writer = download::write_all(content, writer).await?;
+
+        Ok((file, writer))
+    }
+
+    /// Downloads a file and stores it at `path`, an existing file is overwritten
+    pub async fn download_file_to_path<P: AsRef<Path>>(&self, file_id: String, path: P) -> Result<objects::File, Error> {
+        let (file, content) = self.download_file(file_id).await?;
+
+        let writer = tokio::fs::File::create(path).await.context(ErrorKind::IO)?;
+        download::write_all(content, writer).await?;
+
+        Ok(file)
+    }
+
     /// Creates a new request with some byte content (e.g. a file). The method properties have to be
     /// in the formdata setup and cannot be sent as JSON.
-    pub fn fetch_formdata(
+    pub async fn fetch_formdata(
         &self,
         func: &'static str,
         msg: &Value,
         files: Vec<File>,
-        kind: &str,
-    ) -> impl Future<Item = String, Error = Error> {
-        debug!("Send formdata {}: {}", func, msg.to_string());
+        _kind: &str,
+    ) -> Result<String, Error> {
+        if self.middlewares.is_empty() {
+            return self.deliver_formdata(func, msg, files).await;
+        }
 
-        let request = self.build_formdata(func, msg, files, kind).unwrap();
-        _fetch(self.inner.request(request))
+        let mut body = msg.clone();
+        self.before_request(func, &mut body)?;
+
+        let result = self.deliver_formdata(func, &body, files).await;
+        self.after_request(func, &result);
+
+        result
     }
 
-    /// Builds the HTTP header for a formdata request. The file content is read and then append to
-    /// the formdata. Each key-value pair has a own line.
-    fn build_formdata(
+    /// Sends a formdata message and repeats it after a migration
+    async fn deliver_formdata(
         &self,
         func: &'static str,
         msg: &Value,
         files: Vec<File>,
-        _kind: &str,
-    ) -> Result<Request<Body2>,Error> {
-        let url: Result<Uri, _> =
-            format!("https://api.telegram.org/bot{}/{}", self.key, func).parse();
+    ) -> Result<String, Error> {
+        debug!("Send formdata {}: {}", func, msg);
 
-        let mut req_builder = Request::post(url.context(ErrorKind::Uri)?);
-        let mut form = multipart::Form::default();
+        // the files can only be sent once, unless they are buffered for another attempt
+        if self.retry.is_none() && !self.follow_migrations {
+            throttle(&self.limiter, func, Some(msg)).await;
 
-        let msg = msg.as_object().ok_or(ErrorKind::JsonNotMap)?;
+            let answer = self.inner.fetch_formdata(self.function_url(func), func, msg, files).await?;
 
-        // add properties
-        for (key, val) in msg.iter() {
-            let val = match val {
-                &Value::String(ref val) => format!("{}", val),
-                etc => format!("{}", etc),
-            };
+            return parse_answer(&answer);
+        }
+
+        let files = Arc::new(ReplayableFiles::new(files).await?);
+
+        let err = match self.send_formdata(func, msg.clone(), files.clone()).await {
+            Ok(answer) => return Ok(answer),
+            Err(err) => err
+        };
 
-            form.add_text(key, val);
+        match self.migrate(func, &err, msg.clone()) {
+            Some((msg, migration)) => {
+                self.notify_migration(migration).await;
+
+                self.send_formdata(func, msg, files).await
+            }
+            None => Err(err)
         }
+    }
 
-        for file in files {
-            match file {
-                File::Memory { name, source } => {
-                    form.add_reader_file(name.clone(), source, name);
-                }
-                File::Disk { path } => {
-                    form.add_file(path.clone().file_name().unwrap().to_str().unwrap(), path).context(ErrorKind::NoFile)?;
-                },
-                _ => {}
+    /// Sends a formdata message with the rate limiter and retry policy of this handle
+    async fn send_formdata(
+        &self,
+        func: &'static str,
+        msg: Value,
+        files: Arc<ReplayableFiles>,
+    ) -> Result<String, Error> {
+        let inner = self.inner.clone();
+        let url = self.function_url(func);
+        let limiter = self.limiter.clone();
+
+        let send = move || {
+            let (inner, url, files) = (inner.clone(), url.clone(), files.files());
+            let (limiter, msg) = (limiter.clone(), msg.clone());
+
+            async move {
+                throttle(&limiter, func, Some(&msg)).await;
+
+                let answer = inner.fetch_formdata(url, func, &msg, files).await?;
+
+                parse_answer(&answer)
             }
+        };
+
+        match self.retry {
+            Some(ref policy) => FutureRetry::new(send, policy.handler(func)).await
+                .map(|(answer, _)| answer)
+                .map_err(|(err, _)| err),
+            None => send().await
         }
+    }
+
+    /// Returns the message addressed to the new chat, if the request failed because the group
+    /// has been upgraded to a supergroup and migrations are followed
+    fn migrate(&self, func: &'static str, err: &Error, mut msg: Value) -> Option<(Value, ChatMigration)> {
+        if !self.follow_migrations {
+            return None;
+        }
+
+        let to_chat_id = TelegramError::find(err).and_then(TelegramError::migrate_to_chat_id)?;
+        let from_chat_id = msg.get("chat_id").and_then(Value::as_i64)?;
 
-        let req = form.set_body_convert::<Body2, Body>(&mut req_builder).context(ErrorKind::Hyper)?;
+        debug!("Resend {} to the migrated chat {}", func, to_chat_id);
 
-        Ok(req)
+        msg["chat_id"] = Value::from(to_chat_id);
+
+        Some((msg, ChatMigration { from_chat_id, to_chat_id }))
+    }
+}
+
+/// Waits for a free slot of the rate limiter, if there is one
+async fn throttle(limiter: &Option<RateLimiter>, func: &str, msg: Option<&Value>) {
+    if let (Some(limiter), Some(msg)) = (limiter, msg) {
+        limiter.wait(func, msg).await;
     }
 }
-/// Calls the Telegram API for the function and awaits the result. The result is then converted
-/// to a String and returned in a Future.
-//pub fn _fetch<T: futures_retry::ErrorHandler<std::io::Error>>(fut_res: FutureRetry<FnMut() -> ResponseFuture, T>) -> impl Future<Item = String, Error = Error> {
-//pub fn _fetch<T: Future<Item=hyper::Response<hyper::Body>, Error=hyper::error::Error>>(fut_res: T) -> impl Future<Item = String, Error = Error> {
-pub fn _fetch(fut_res: ResponseFuture) -> impl Future<Item = String, Error = Error> {
-    fut_res
-        .and_then(move |res| res.into_body().concat2())
-        .map_err(|e| {
-            eprintln!("{:?}", e);
-
-            Error::from(e.context(ErrorKind::Hyper))
-        })
-        .and_then(move |response_chunks| {
-            let s = str::from_utf8(&response_chunks)?;
 
-            debug!("Got a result from telegram: {}", s);
-            // try to parse the result as a JSON and find the OK field.
-            // If the ok field is true, then the string in "result" will be returned
-            let req = serde_json::from_str::<Value>(&s).context(ErrorKind::JsonParse)?;
+/// Parses the answer of the Telegram server. If the request was successful, the content of the
+/// result field is returned as a String.
+pub fn parse_answer(answer: &str) -> Result<String, Error> {
+    debug!("Got a result from telegram: {}", answer);
+    // try to parse the result as a JSON and find the OK field.
+    // If the ok field is true, then the string in "result" will be returned
+    let req = serde_json::from_str::<Value>(answer).context(ErrorKind::JsonParse)?;
 
-            let ok = req.get("ok")
-                .and_then(Value::as_bool)
-                .ok_or(ErrorKind::Json)?;
+    let ok = req.get("ok")
+        .and_then(Value::as_bool)
+        .ok_or(ErrorKind::Json)?;
 
-            if ok {
-                if let Some(result) = req.get("result") {
-                    return Ok(serde_json::to_string(result).context(ErrorKind::JsonSerialize)?);
-                }
-            }
+    if ok {
+        if let Some(result) = req.get("result") {
+            return Ok(serde_json::to_string(result).context(ErrorKind::JsonSerialize)?);
+        }
+    }
 
-            let e = match req.get("description").and_then(Value::as_str) {
-                Some(err) => {
-                    Error::from(TelegramError::new(err.into()).context(ErrorKind::Telegram))
-                }
-                None => Error::from(ErrorKind::Telegram),
-            };
+    let e = match req.get("description").and_then(Value::as_str) {
+        Some(err) => {
+            let error_code = req.get("error_code").and_then(Value::as_i64);
+            let parameters = req.get("parameters")
+                .and_then(|x| serde_json::from_value::<objects::ResponseParameter>(x.clone()).ok());
 
-            Err(Error::from(e.context(ErrorKind::Telegram)))
-        })
+            Error::from(TelegramError::with_details(err.into(), error_code, parameters).context(ErrorKind::Telegram))
+        }
+        None => Error::from(ErrorKind::Telegram),
+    };
+
+    Err(Error::from(e.context(ErrorKind::Telegram)))
+}
+
+/// Passes a guarded command to its handler or sends the denial reply
+async fn check_guard((bot, msg, guard, deliver): GuardJob, cache: Arc<MemberCache>) {
+    use crate::functions::FunctionSendMessage;
+
+    match guard.check(&msg, &bot, &cache).await {
+        Ok(true) => return deliver(bot, msg).await,
+        Ok(false) => {}
+        Err(err) => warn!("Could not check the permission of a command: {}", err),
+    }
+
+    if let Some(text) = guard.denial() {
+        let reply = bot.message(msg.chat.id, text.into())
+            .reply_to_message_id(msg.message_id)
+            .send()
+            .await;
+
+        if let Err(err) = reply {
+            warn!("Could not deny a command: {}", err);
+        }
+    }
 }
 
 /// The main bot structure
 ///
-/// Contains all configuration like `key`, `name`, etc. important handles to message the user and
+/// Contains all configuration like `name`, `timeout`, etc. important handles to message the user and
 /// `request` to issue requests to the Telegram server
 #[derive(Clone)]
 pub struct Bot {
     pub request: RequestHandle,
-    key: String,
     name: Option<String>,
     update_interval: u64,
     timeout: u64,
-    pub handlers: HashMap<String, UnboundedSender<(RequestHandle, objects::Message)>>,
-    pub unknown_handler: Option<UnboundedSender<(RequestHandle, objects::Message)>>,
-    pub callback_handler: Option<UnboundedSender<(RequestHandle, objects::CallbackQuery)>>,
-    pub inline_handler: Option<UnboundedSender<(RequestHandle, objects::InlineQuery)>>
+    custom_transport: bool,
+    pub handlers: HashMap<String, HandlerSender<(RequestHandle, objects::Message)>>,
+    pub unknown_handler: Option<HandlerSender<(RequestHandle, objects::Message)>>,
+    pub message_handlers: Vec<(MessageFilter, HandlerSender<(RequestHandle, objects::Message)>)>,
+    pub routes: Vec<(Route, HandlerSender<(RequestHandle, objects::Message)>)>,
+    command_sets: Vec<(Vec<&'static str>, CommandSink)>,
+    registry: Vec<CommandInfo>,
+    help: bool,
+    help_header: Option<String>,
+    help_handler: Option<HandlerSender<(RequestHandle, objects::Message)>>,
+    sync_commands: bool,
+    dialogues: Vec<Arc<dyn DialogueRouter>>,
+    session_store: Option<Arc<Sessions>>,
+    session_scope: SessionScope,
+    guards: HashMap<String, Arc<Guard>>,
+    guard_handler: Option<HandlerSender<GuardJob>>,
+    member_cache: Arc<MemberCache>,
+    queues: Queues,
+    processing: Processing,
+    lanes: Option<Arc<Lanes>>,
+    pub callback_routes: Vec<(String, HandlerSender<(RequestHandle, objects::CallbackQuery)>)>,
+    pub callback_handler: Option<HandlerSender<(RequestHandle, objects::CallbackQuery)>>,
+    pub inline_handler: Option<HandlerSender<(RequestHandle, objects::InlineQuery)>>,
+    pub chosen_inline_handler: Option<HandlerSender<(RequestHandle, objects::ChosenInlineResult)>>,
+    pub edited_message_handler: Option<HandlerSender<(RequestHandle, objects::Message)>>,
+    pub channel_post_handler: Option<HandlerSender<(RequestHandle, objects::Message)>>,
+    pub edited_channel_post_handler: Option<HandlerSender<(RequestHandle, objects::Message)>>,
+    webhook: Option<Webhook>,
+    delete_webhook: bool,
+    shutdown: ShutdownHandle,
+    shutdown_timeout: Duration,
+    offset_store: Option<Arc<dyn OffsetStore>>,
+    allowed_updates: Option<Vec<String>>,
+    catch_all: bool
 }
 
 impl Bot {
     pub fn new(key: &str) -> Bot {
-        let client = Client::builder()
-                .keep_alive(true)
-                .keep_alive_timeout(Some(Duration::from_secs(3600)))
-                .build(HttpsConnector::new(4).unwrap());
+        let client = HyperTransport::new(Duration::from_secs(3600));
 
         Bot {
-            request: RequestHandle { inner: Arc::new(client), key: key.into() },
-            key: key.into(),
+            request: RequestHandle::new(key, Arc::new(client)),
             name: None,
             update_interval: 2000,
             timeout: 3600,
+            custom_transport: false,
             handlers: HashMap::new(),
             unknown_handler: None,
+            message_handlers: Vec::new(),
+            routes: Vec::new(),
+            command_sets: Vec::new(),
+            registry: Vec::new(),
+            help: true,
+            help_header: None,
+            help_handler: None,
+            sync_commands: true,
+            dialogues: Vec::new(),
+            session_store: None,
+            session_scope: SessionScope::Chat,
+            guards: HashMap::new(),
+            guard_handler: None,
+            member_cache: Arc::new(MemberCache::new(Duration::from_secs(300))),
+            queues: Queues::default(),
+            processing: Processing::Concurrent,
+            lanes: None,
+            callback_routes: Vec::new(),
             callback_handler: None,
-            inline_handler: None
+            inline_handler: None,
+            chosen_inline_handler: None,
+            edited_message_handler: None,
+            channel_post_handler: None,
+            edited_channel_post_handler: None,
+            webhook: None,
+            delete_webhook: false,
+            shutdown: ShutdownHandle::new(),
+            shutdown_timeout: Duration::from_secs(10),
+            offset_store: None,
+            allowed_updates: None,
+            catch_all: true
         }
     }
 
@@ -206,212 +538,1263 @@ impl Bot {
     }
 
     /// Sets the timeout interval for long polling
+    ///
+    /// Idle connections of the default hyper client are kept alive as long, a transport set with
+    /// `transport` is kept as it is.
     pub fn timeout(mut self, timeout: u64) -> Bot {
         self.timeout = timeout;
 
-        let client = Client::builder()
-                .keep_alive(true)
-                .keep_alive_timeout(Some(Duration::from_secs(timeout)))
-                .build(HttpsConnector::new(4).unwrap());
+        if !self.custom_transport {
+            self.request.inner = Arc::new(HyperTransport::new(Duration::from_secs(timeout)));
+        }
+
+        self
+    }
 
-        self.request = RequestHandle { inner: Arc::new(client), key: self.key.clone() };
+    /// Repeats requests which failed because of flood control or network errors
+    ///
+    /// The policy applies to every function sent with the request handle of this bot. It waits for
+    /// `retry_after` seconds if Telegram answers with error 429.
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Bot {
+        self.request = self.request.retry_policy(policy);
 
         self
     }
 
-    /// Creates a new command and returns a stream which will yield a message when the command is send
-    pub fn new_cmd(
-        &mut self,
-        cmd: &str,
-    ) -> impl Stream<Item = (RequestHandle, objects::Message), Error = Error> {
-        let (sender, receiver) = mpsc::unbounded();
+    /// Delays outgoing messages to stay within the rate limits of Telegram
+    ///
+    /// Requests are queued instead of failing with error 429, see `RateLimiter` for the default
+    /// limits. All clones of the request handle share the same limiter.
+    pub fn rate_limiter(mut self, limiter: RateLimiter) -> Bot {
+        self.request = self.request.rate_limiter(limiter);
 
-        let cmd = if cmd.starts_with("/") {
-            cmd.into()
-        } else {
-            format!("/{}", cmd)
-        };
+        self
+    }
 
-        self.handlers.insert(cmd.into(), sender);
+    /// Resends requests to a group which has been upgraded to a supergroup to the new chat id
+    ///
+    /// Each migration is reported to the stream returned by `migrations`.
+    pub fn follow_migrations(mut self, follow: bool) -> Bot {
+        self.request = self.request.follow_migrations(follow);
 
-        receiver.map_err(|_| Error::from(ErrorKind::Channel))
+        self
     }
 
-    /// Returns a stream which will yield a message when none of previously registered commands matches
-    pub fn unknown_cmd(&mut self) -> impl Stream<Item = (RequestHandle, objects::Message), Error = Error> {
-        let (sender, receiver) = mpsc::unbounded();
+    /// Replaces the transport which delivers requests to the Telegram server, e.g. with a
+    /// `MockTransport` in tests
+    pub fn transport<T: Transport + 'static>(mut self, transport: T) -> Bot {
+        self.request.inner = Arc::new(transport);
+        self.custom_transport = true;
 
-        self.unknown_handler = Some(sender);
+        self
+    }
 
-        receiver.then(|x| x.map_err(|_| Error::from(ErrorKind::Channel)))
+    /// Sets the URL of the Bot API server, defaults to `https://api.telegram.org`
+    ///
+    /// This allows to use a self-hosted telegram-bot-api server or a local test double. Plain
+    /// `http://` URLs are supported as well.
+    pub fn api_url(mut self, url: &str) -> Bot {
+        self.request.base_url = url.trim_end_matches('/').into();
+
+        self
     }
 
-    /// Returns a stream which will yield a received CallbackQuery
-    pub fn callback(&mut self) -> impl Stream<Item = (RequestHandle, objects::CallbackQuery), Error = Error> {
-        let (sender, receiver) = mpsc::unbounded();
+    /// Receives updates with a local webhook listener instead of polling `getUpdates`
+    ///
+    /// The listener has to be registered with Telegram (e.g. behind a reverse proxy) with the
+    /// setWebhook function. Handlers are called in the same way as with long polling.
+    pub fn webhook(mut self, webhook: Webhook) -> Bot {
+        self.webhook = Some(webhook);
 
-        self.callback_handler = Some(sender);
+        self
+    }
 
-        receiver.then(|x| x.map_err(|_| Error::from(ErrorKind::Channel)))
+    /// Deletes an active webhook before long polling begins
+    ///
+    /// Telegram refuses getUpdates requests as long as a webhook is registered, so this should be
+    /// set when a bot switches from webhook to polling mode.
+    pub fn delete_webhook_on_start(mut self, delete: bool) -> Bot {
+        self.delete_webhook = delete;
+
+        self
     }
 
-    /// Returns a stream which will yield a received CallbackQuery
-    pub fn inline(&mut self) -> impl Stream<Item = (RequestHandle, objects::InlineQuery), Error = Error> {
-        let (sender, receiver) = mpsc::unbounded();
+    /// Returns a handle which stops the update loop of this bot and all its clones
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
 
-        self.inline_handler = Some(sender);
+    /// Sets the time the handlers get to finish their work after a shutdown, defaults to ten
+    /// seconds
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> Bot {
+        self.shutdown_timeout = timeout;
 
-        receiver.then(|x| x.map_err(|_| Error::from(ErrorKind::Channel)))
+        self
     }
 
-    pub fn resolve_name(&self) -> impl Future<Item = Option<String>, Error = Error> {
-        use crate::functions::FunctionGetMe;
+    /// Keeps the offset of the next update in a store, so that updates are not handled again after
+    /// a restart
+    ///
+    /// The offset is loaded when the update loop starts and saved after every dispatched update.
+    pub fn offset_store<S: OffsetStore + 'static>(mut self, store: S) -> Bot {
+        self.offset_store = Some(Arc::new(store));
 
-        // create a new task which resolves the bot name and then set it in the struct
-        let resolve_name = self.request.get_me().send()
-            .map(move |user| {
-                if let Some(name) = user.1.username {
-                    return Some(format!("@{}", name));
-                } else {
-                    return None;
-                }
-            });
+        self
+    }
+
+    /// Loads the session of every dispatched update from the store, see `RequestHandle::session`
+    ///
+    /// The updates of a chat should be processed with `Processing::SequentialPerChat`, otherwise
+    /// the changes of concurrent updates overwrite each other.
+    pub fn session_store<S: SessionStore + 'static>(mut self, store: S) -> Bot {
+        self.session_store = Some(Arc::new(Sessions::new(Arc::new(store))));
 
-        resolve_name
+        self
     }
 
-    pub fn process_updates(self, last_id: Arc<AtomicUsize>) -> impl Stream<Item = (RequestHandle, objects::Update), Error = Error> {
-        use crate::functions::FunctionGetUpdates;
+    /// Keeps a session per chat or per user, the default is per chat
+    pub fn session_scope(mut self, scope: SessionScope) -> Bot {
+        self.session_scope = scope;
 
-        self.request.get_updates()
-            .offset(last_id.load(Ordering::Relaxed) as i64)
-            .timeout(self.timeout as i64)
-            .send()
-            .into_stream()
-            .map(|(_, x)| {
-                stream::iter_result(
-                    x.0
-                        .into_iter()
-                        .map(|x| Ok(x))
-                        .collect::<Vec<Result<objects::Update, Error>>>(),
-                )
-            })
-            .flatten()
-            .and_then(move |x| {
-                if last_id.load(Ordering::Relaxed) < x.update_id as usize + 1 {
-                    last_id.store(x.update_id as usize + 1, Ordering::Relaxed);
-                }
+        self
+    }
 
-                Ok(x)
-            })
-        .filter_map(move |mut val| {
-            debug!("Got an update from Telegram: {:?}", val);
-    
-            if let Some(_) = val.callback_query {
-                if let Some(sender) = self.callback_handler.clone() {
-                    sender
-                        .unbounded_send((self.request.clone(), val.callback_query.unwrap()))
-                        .unwrap_or_else(|e| error!("Error: {}", e));
-                    return None;
-                }
-            }
-    
-            if let Some(_) = val.inline_query {
-                if let Some(sender) = self.inline_handler.clone() {
-                    sender
-                        .unbounded_send((self.request.clone(), val.inline_query.unwrap()))
-                        .unwrap_or_else(|e| error!("Error: {}", e));
-                    return None;
-                }
-            }
+    /// Adds a middleware which sees every update before it is dispatched and every request
+    pub fn middleware<M: Middleware + 'static>(mut self, middleware: M) -> Bot {
+        self.request = self.request.middleware(Arc::new(middleware));
 
-            let mut sndr: Option<UnboundedSender<(RequestHandle, objects::Message)>> = None;
-    
-            if let Some(ref mut message) = val.message {
-                if let Some(true) = message.entities.as_ref().and_then(|x| x.get(0)).map(|x| x.kind == "bot_command") {
-                    if let Some(text) = message.text.clone() {
-                        let mut content = text.split_whitespace();
-                        if let Some(mut cmd) = content.next() {
-                            if let Some(name) = self.name.as_ref() {
-                                if cmd.ends_with(name.as_str()) {
-                                    cmd = cmd.rsplitn(2, '@').skip(1).next().unwrap();
-                                }
-                            }
-                            if let Some(sender) = self.handlers.get(cmd)
-                            {
-                                sndr = Some(sender.clone());
-                                message.text = Some(content.collect::<Vec<&str>>().join(" "));
-                            } else if let Some(ref sender) =
-                                self.unknown_handler
-                            {
-                                sndr = Some(sender.clone());
-                            }
-                        }
-                    }
-                }
-            }
-    
-            if let Some(sender) = sndr {
-                sender
-                    .unbounded_send((self.request.clone(), val.message.unwrap()))
-                    .unwrap_or_else(|e| error!("Error: {}", e));
-                return None;
-            } else {
-                return Some((self.request.clone(), val));
-            }
-        })
+        self
+    }
+
+    /// Sets how long the administrators of a chat and the status of its members are cached for
+    /// guards, five minutes by default
+    pub fn member_cache_ttl(mut self, ttl: Duration) -> Bot {
+        self.member_cache = Arc::new(MemberCache::new(ttl));
+
+        self
     }
 
+    /// Sets how many updates may wait in the queue of each stream, 100 by default
     ///
-    /// The main update loop, the update function is called every update_interval milliseconds
-    /// When an update is available the last_id will be updated and the message is filtered
-    /// for commands
-    /// The message is forwarded to the returned stream if no command was found
-    pub fn get_stream(
-        mut self,
-        name: Option<String>
-    ) -> impl Stream<Item = (RequestHandle, objects::Update), Error = Error> { 
-        self.name = name;
-        let last_id = Arc::new(AtomicUsize::new(0));
+    /// An update for a full queue waits until its handler takes an item, updates for other
+    /// handlers are not held back by it, see the `queue` module.
+    pub fn handler_capacity(self, capacity: usize) -> Bot {
+        self.queues.set_capacity(capacity);
 
-        let duration = Duration::from_millis(self.update_interval);
-        Interval::new(Instant::now(), duration)
-            .map_err(|x| Error::from(x.context(ErrorKind::IntervalTimer)))
-            .map(move |_| self.clone().process_updates(last_id.clone()))
-            .flatten()
-            /*.inspect_err(|err| println!("Error on {:?}", err))
-            .then(|r| futures::future::ok(stream::iter_ok::<_, Error>(r)))
-            .flatten()*/
+        self
     }
 
-    pub fn into_future(&self) -> impl Future<Item = (), Error = Error> {
-        let bot = self.clone();
+    /// Sets whether the updates of a chat are processed concurrently or one after another
+    pub fn processing(mut self, processing: Processing) -> Bot {
+        self.processing = processing;
 
-        self.resolve_name()
-            .and_then(|name| bot.get_stream(name).for_each(|_| Ok(())))
-            .map(|_| ())
+        self
     }
 
-    pub fn run_with<I>(self, other: I) 
-    where
-        I: IntoFuture<Error = Error>,
-        <I as IntoFuture>::Future: Send + 'static,
-        <I as IntoFuture>::Item: Send
-    {
-        tokio::run(
-            self.into_future().join(other)
-            .map(|_| ())
-            .map_err(|e| {
-                eprintln!("Error: could not resolve the bot name!");
-
-                for (i, cause) in e.iter_causes().enumerate() {
-                    println!(" => {}: {}", i, cause);
-                }
-            })
-        );
+    /// Returns a handle to inspect the queues of all streams
+    pub fn queues(&self) -> Queues {
+        self.queues.clone()
+    }
+
+    /// Answers /help with the list of registered commands, enabled by default
+    ///
+    /// The reply is only sent if commands were registered with `command` or `commands` and no
+    /// handler for /help exists.
+    pub fn help(mut self, enabled: bool) -> Bot {
+        self.help = enabled;
+
+        self
+    }
+
+    /// Sets the first line of the /help reply
+    pub fn help_header(mut self, header: &str) -> Bot {
+        self.help_header = Some(header.into());
+
+        self
+    }
+
+    /// Publishes the registered commands with setMyCommands on startup, enabled by default
+    pub fn sync_commands(mut self, enabled: bool) -> Bot {
+        self.sync_commands = enabled;
+
+        self
+    }
+
+    /// Sets the update types which are requested from Telegram, e.g. `message` or
+    /// `callback_query`
+    ///
+    /// By default `run_with` requests only the types for which a stream was registered, see
+    /// `registered_updates`.
+    pub fn allowed_updates(mut self, kinds: Vec<String>) -> Bot {
+        self.allowed_updates = Some(kinds);
+
+        self
+    }
+
+    /// Returns the update types for which a stream is registered
+    ///
+    /// This list can be passed to setWebhook, so that Telegram only pushes updates which are
+    /// handled.
+    pub fn registered_updates(&self) -> Vec<String> {
+        let mut kinds = Vec::new();
+
+        if !self.handlers.is_empty()
+            || self.unknown_handler.is_some()
+            || !self.message_handlers.is_empty()
+            || !self.routes.is_empty()
+            || !self.command_sets.is_empty()
+            || !self.dialogues.is_empty()
+            || self.request.migrations.is_some()
+        {
+            kinds.push("message".into());
+        }
+
+        if self.callback_handler.is_some() || !self.callback_routes.is_empty() || !self.dialogues.is_empty() {
+            kinds.push("callback_query".into());
+        }
+
+        if self.inline_handler.is_some() {
+            kinds.push("inline_query".into());
+        }
+
+        if self.chosen_inline_handler.is_some() {
+            kinds.push("chosen_inline_result".into());
+        }
+
+        if self.edited_message_handler.is_some() {
+            kinds.push("edited_message".into());
+        }
+
+        if self.channel_post_handler.is_some() {
+            kinds.push("channel_post".into());
+        }
+
+        if self.edited_channel_post_handler.is_some() {
+            kinds.push("edited_channel_post".into());
+        }
+
+        kinds
+    }
+
+    /// The update types requested with getUpdates. An empty list stands for all types except
+    /// those which Telegram only sends on request.
+    fn requested_updates(&self) -> Vec<String> {
+        match self.allowed_updates {
+            Some(ref kinds) => kinds.clone(),
+            // every update reaches the stream returned by get_stream
+            None if self.catch_all => Vec::new(),
+            None => self.registered_updates(),
+        }
+    }
+
+    /// Creates a new command and returns a stream which will yield a message when the command is send
+    pub fn new_cmd(
+        &mut self,
+        cmd: &str,
+    ) -> impl Stream<Item = Result<(RequestHandle, objects::Message), Error>> {
+        let cmd = if cmd.starts_with('/') {
+            cmd.into()
+        } else {
+            format!("/{}", cmd)
+        };
+
+        let (sender, receiver) = self.queues.channel(&cmd);
+
+        self.handlers.insert(cmd, sender);
+
+        receiver.map(Ok)
+    }
+
+    /// Registers a command with its description and returns a stream like `new_cmd`
+    ///
+    /// The command is listed in the /help reply and in the command menu of the Telegram client,
+    /// unless it is hidden.
+    pub fn command(&mut self, info: CommandInfo) -> impl Stream<Item = Result<(RequestHandle, objects::Message), Error>> {
+        let stream = self.new_cmd(&info.name);
+
+        self.registry.push(info);
+
+        stream
+    }
+
+    /// Returns all registered commands, including /help if it is answered automatically
+    pub fn registered_commands(&self) -> Vec<CommandInfo> {
+        let mut commands = self.registry.clone();
+
+        if self.auto_help() {
+            commands.push(CommandInfo::new("/help", "Shows all commands"));
+        }
+
+        commands
+    }
+
+    /// Checks whether /help is answered with the list of registered commands
+    fn auto_help(&self) -> bool {
+        self.help
+            && !self.registry.is_empty()
+            && !self.handlers.contains_key("/help")
+            && !self.command_sets.iter().any(|(names, _)| names.contains(&"/help"))
+    }
+
+    /// Returns the /help reply for chats of this scope
+    pub fn help_text(&self, scope: CommandScope) -> String {
+        let mut text = self.help_header.clone().unwrap_or_else(|| "These commands are available:".into());
+
+        for command in self.registered_commands().into_iter().filter(|x| !x.hidden && x.scope.includes(scope)) {
+            if command.description.is_empty() {
+                text.push_str(&format!("\n{}", command.name));
+            } else {
+                text.push_str(&format!("\n{} - {}", command.name, command.description));
+            }
+        }
+
+        text
+    }
+
+    /// Publishes the registered commands to the command menu of the Telegram client
+    ///
+    /// Every scope gets the visible commands which are offered there, scopes without own commands
+    /// are deleted so that Telegram falls back to the broader scope. Commands without description
+    /// can't be published and are only listed in /help.
+    pub async fn publish_commands(&self) -> Result<(), Error> {
+        use crate::functions::{FunctionSetMyCommands, FunctionDeleteMyCommands};
+
+        let commands = self.registered_commands();
+        let scopes = [CommandScope::All, CommandScope::Private, CommandScope::Groups, CommandScope::Admins];
+
+        for scope in scopes.iter().cloned() {
+            let visible: Vec<&CommandInfo> = commands.iter()
+                .filter(|x| !x.hidden && !x.description.is_empty() && x.scope.includes(scope))
+                .collect();
+
+            if !visible.iter().any(|x| x.scope == scope) {
+                self.request.delete_my_commands().scope(scope.to_scope()).send().await?;
+                continue;
+            }
+
+            let list = visible.into_iter()
+                .map(|x| objects::BotCommand {
+                    command: x.name.trim_start_matches('/').into(),
+                    description: x.description.clone(),
+                })
+                .collect();
+
+            self.request.set_my_commands(list).scope(scope.to_scope()).send().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Answers /help until the bot is dropped
+    fn serve_help(&mut self) -> impl Future<Output = ()> {
+        use crate::functions::FunctionSendMessage;
+
+        let (sender, receiver) = self.queues.channel::<(RequestHandle, objects::Message)>("/help");
+
+        // without a sender the receiver ends at once
+        if self.auto_help() {
+            self.help_handler = Some(sender);
+        }
+
+        let private = self.help_text(CommandScope::Private);
+        let groups = self.help_text(CommandScope::Groups);
+
+        receiver.for_each(move |(bot, msg)| {
+            let text = match msg.chat.kind.as_str() {
+                "private" => private.clone(),
+                _ => groups.clone(),
+            };
+
+            async move {
+                if let Err(err) = bot.message(msg.chat.id, text).send().await {
+                    warn!("Could not answer /help: {}", err);
+                }
+            }
+        })
+    }
+
+    /// Checks guarded commands which need the status of their sender or a denial reply until the
+    /// bot is dropped
+    fn serve_guards(&mut self) -> impl Future<Output = ()> {
+        let (sender, receiver) = self.queues.channel::<GuardJob>("guard checks");
+
+        // without a sender the receiver ends at once
+        if !self.guards.is_empty() {
+            self.guard_handler = Some(sender);
+        }
+
+        let cache = self.member_cache.clone();
+
+        // a slow getChatMember request shouldn't hold back the commands of other chats, checks
+        // of the same chat share their requests in the cache
+        receiver.for_each_concurrent(GUARD_CHECKS, move |job| check_guard(job, cache.clone()))
+    }
+
+    /// Passes a command to its handler if the guard allows it, the check is done by
+    /// `serve_guards` unless it needs no request
+    ///
+    /// Without the update loop of `into_future` the check runs on a task of its own.
+    async fn guard_command(&self, guard: &Arc<Guard>, request: RequestHandle, msg: objects::Message, deliver: Deliver) {
+        if let Some(true) = guard.check_now(&msg) {
+            return deliver(request, msg).await;
+        }
+
+        let job = (request, msg, guard.clone(), deliver);
+
+        match self.guard_handler {
+            Some(ref sender) => sender
+                .send_when_room(job)
+                .await
+                .unwrap_or_else(|e| error!("Error: {}", e)),
+            None => drop(tokio::spawn(check_guard(job, self.member_cache.clone()))),
+        }
+    }
+
+    /// Returns a stream which will yield every command of the set `C` with its parsed arguments
+    ///
+    /// Commands with invalid arguments are yielded as well, the error has a message which can be
+    /// sent back to the user. Commands registered with `new_cmd` take precedence.
+    pub fn commands<C>(&mut self) -> impl Stream<Item = Result<(RequestHandle, objects::Message, Result<C, CommandError>), Error>>
+    where
+        C: BotCommands + Send + 'static,
+    {
+        let (sender, receiver) = self.queues.channel(&C::names().join(", "));
+
+        let sink: CommandSink = Arc::new(move |bot, msg, name, args| {
+            let (sender, command) = (sender.clone(), C::parse(name, args));
+
+            async move {
+                sender
+                    .send_when_room((bot, msg, command))
+                    .await
+                    .unwrap_or_else(|e| error!("Error: {}", e));
+            }.boxed()
+        });
+
+        self.command_sets.push((C::names(), sink));
+        self.registry.extend(C::descriptions());
+
+        receiver.map(Ok)
+    }
+
+    /// Restricts a command to the users which pass the guard
+    ///
+    /// The guard applies to commands registered with `new_cmd`, `command` and `commands` and to
+    /// the commands which start or cancel a dialogue. Users who are not allowed get the denial
+    /// reply of the guard, their command never reaches the stream. A later guard for the same command replaces the earlier one.
+    ///
+    /// The checks which need the status of the sender run on the update loop of `into_future`.
+    /// With `get_stream` or `get_webhook_stream` each of them is spawned on the runtime.
+    pub fn guard(&mut self, cmd: &str, guard: Guard) {
+        self.guards.insert(command::normalize(cmd), Arc::new(guard));
+    }
+
+    /// Returns the cache of chat administrators and member statuses which the guards use
+    ///
+    /// Invalidate a chat after changing its administrators, so that guards notice at once.
+    pub fn member_cache(&self) -> Arc<MemberCache> {
+        self.member_cache.clone()
+    }
+
+    /// Returns a stream which will yield the inputs of all active conversations of the dialogue
+    ///
+    /// Messages and callback queries of active conversations are sent to this stream before any
+    /// other handler sees them. Entry and cancel commands with a guard reach the stream only if
+    /// the guard allows them. Keep a clone of the dialogue to start or change conversations from
+    /// other handlers.
+    pub fn dialogue<S>(&mut self, dialogue: Dialogue<S>) -> impl Stream<Item = Result<(RequestHandle, DialogueUpdate<S>), Error>>
+    where
+        S: Clone + Send + Sync + 'static,
+    {
+        let (sender, receiver) = self.queues.channel("dialogue");
+
+        self.dialogues.push(Arc::new(dialogue::Router { dialogue, sender }));
+
+        receiver.map(Ok)
+    }
+
+    /// Ends conversations which timed out until the bot is dropped
+    fn expire_dialogues(&self) -> impl Future<Output = ()> {
+        let dialogues: Vec<_> = self.dialogues.iter().filter(|x| x.has_timeout()).cloned().collect();
+        let request = self.request.clone();
+
+        async move {
+            if dialogues.is_empty() {
+                return future::pending().await;
+            }
+
+            let mut interval = time::interval(Duration::from_secs(1));
+
+            loop {
+                interval.tick().await;
+
+                let now = Instant::now();
+
+                for dialogue in &dialogues {
+                    dialogue.expire(&request, now).await;
+                }
+            }
+        }
+    }
+
+    /// Returns a stream which will yield a message when none of previously registered commands matches
+    pub fn unknown_cmd(&mut self) -> impl Stream<Item = Result<(RequestHandle, objects::Message), Error>> {
+        let (sender, receiver) = self.queues.channel("unknown commands");
+
+        self.unknown_handler = Some(sender);
+
+        receiver.map(Ok)
+    }
+
+    /// Returns a stream which will yield every message that is not a command and passes the filter
+    ///
+    /// A message is sent to the first registered stream whose filter matches.
+    pub fn messages(&mut self, filter: MessageFilter) -> impl Stream<Item = Result<(RequestHandle, objects::Message), Error>> {
+        let (sender, receiver) = self.queues.channel("messages");
+
+        self.message_handlers.push((filter, sender));
+
+        receiver.map(Ok)
+    }
+
+    /// Returns a stream which will yield every message selected by the route
+    ///
+    /// Routes are checked after the commands registered with `new_cmd`, by descending priority and
+    /// then in registration order. Commands which match no route are sent to the unknown
+    /// handler, other messages to the `messages` streams.
+    pub fn route(&mut self, route: Route) -> impl Stream<Item = Result<(RequestHandle, objects::Message), Error>> {
+        let (sender, receiver) = self.queues.channel("routes");
+
+        // keep the routes sorted, routes with the same priority stay in registration order
+        let pos = self.routes.iter()
+            .position(|(x, _)| x.get_priority() < route.get_priority())
+            .unwrap_or(self.routes.len());
+
+        self.routes.insert(pos, (route, sender));
+
+        receiver.map(Ok)
+    }
+
+    /// Returns a stream which will yield every CallbackQuery whose data starts with `prefix`
+    ///
+    /// The longest matching prefix wins, queries without a matching prefix are sent to the
+    /// `callback` stream.
+    pub fn callback_prefix(&mut self, prefix: &str) -> impl Stream<Item = Result<(RequestHandle, objects::CallbackQuery), Error>> {
+        let (sender, receiver) = self.queues.channel(&format!("callback queries {}", prefix));
+
+        let pos = self.callback_routes.iter()
+            .position(|(x, _)| x.len() < prefix.len())
+            .unwrap_or(self.callback_routes.len());
+
+        self.callback_routes.insert(pos, (prefix.into(), sender));
+
+        receiver.map(Ok)
+    }
+
+    /// Returns a stream which will yield a received CallbackQuery
+    pub fn callback(&mut self) -> impl Stream<Item = Result<(RequestHandle, objects::CallbackQuery), Error>> {
+        let (sender, receiver) = self.queues.channel("callback queries");
+
+        self.callback_handler = Some(sender);
+
+        receiver.map(Ok)
+    }
+
+    /// Returns a stream which will yield a received InlineQuery
+    pub fn inline(&mut self) -> impl Stream<Item = Result<(RequestHandle, objects::InlineQuery), Error>> {
+        let (sender, receiver) = self.queues.channel("inline queries");
+
+        self.inline_handler = Some(sender);
+
+        receiver.map(Ok)
+    }
+
+    /// Returns a stream which will yield the inline results chosen by users
+    ///
+    /// Telegram only sends these if inline feedback is enabled with @BotFather.
+    pub fn chosen_inline(&mut self) -> impl Stream<Item = Result<(RequestHandle, objects::ChosenInlineResult), Error>> {
+        let (sender, receiver) = self.queues.channel("chosen inline results");
+
+        self.chosen_inline_handler = Some(sender);
+
+        receiver.map(Ok)
+    }
+
+    /// Returns a stream which will yield the new version of an edited message
+    pub fn edited_message(&mut self) -> impl Stream<Item = Result<(RequestHandle, objects::Message), Error>> {
+        let (sender, receiver) = self.queues.channel("edited messages");
+
+        self.edited_message_handler = Some(sender);
+
+        receiver.map(Ok)
+    }
+
+    /// Returns a stream which will yield every post in a channel the bot is member of
+    pub fn channel_post(&mut self) -> impl Stream<Item = Result<(RequestHandle, objects::Message), Error>> {
+        let (sender, receiver) = self.queues.channel("channel posts");
+
+        self.channel_post_handler = Some(sender);
+
+        receiver.map(Ok)
+    }
+
+    /// Returns a stream which will yield the new version of an edited channel post
+    pub fn edited_channel_post(&mut self) -> impl Stream<Item = Result<(RequestHandle, objects::Message), Error>> {
+        let (sender, receiver) = self.queues.channel("edited channel posts");
+
+        self.edited_channel_post_handler = Some(sender);
+
+        receiver.map(Ok)
+    }
+
+    /// Returns a stream which will yield every group which has been upgraded to a supergroup
+    ///
+    /// Migrations are read from the service messages of both chats and from failed requests to
+    /// the old chat, each one is reported only once.
+    pub fn migrations(&mut self) -> impl Stream<Item = Result<(RequestHandle, ChatMigration), Error>> {
+        let (sender, receiver) = self.queues.channel("migrations");
+
+        self.request.migrations = Some(MigrationNotifier::new(sender));
+
+        receiver.map(Ok)
+    }
+
+    pub async fn resolve_name(&self) -> Result<Option<String>, Error> {
+        use crate::functions::FunctionGetMe;
+
+        let (_, user) = self.request.get_me().send().await?;
+
+        Ok(user.username.map(|name| format!("@{}", name)))
+    }
+
+    /// Removes a registered webhook, if there is one and `delete_webhook_on_start` was set
+    pub async fn prepare_polling(&self) -> Result<(), Error> {
+        use crate::functions::{FunctionGetWebhookInfo, FunctionDeleteWebhook};
+
+        if !self.delete_webhook {
+            return Ok(());
+        }
+
+        let (bot, info) = self.request.get_webhook_info().send().await?;
+
+        if !info.url.is_empty() {
+            info!("Deleting the active webhook {} before polling", info.url);
+
+            bot.delete_webhook().send().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Confirms all updates before `offset` with Telegram, so that they are not delivered again
+    pub async fn commit_offset(&self, offset: i64) -> Result<(), Error> {
+        use crate::functions::FunctionGetUpdates;
+
+        if offset == 0 {
+            return Ok(());
+        }
+
+        debug!("Confirm all updates before {}", offset);
+
+        self.request.get_updates()
+            .offset(offset)
+            .limit(1)
+            .timeout(0)
+            .send()
+            .await?;
+
+        Ok(())
+    }
+
+    pub fn process_updates(self, guard: UpdateGuard) -> impl Stream<Item = Result<(RequestHandle, objects::Update), Error>> {
+        use crate::functions::FunctionGetUpdates;
+
+        self.request.get_updates()
+            .offset(self.confirmed_offset(&guard))
+            .timeout(self.timeout as i64)
+            .allowed_updates(self.requested_updates())
+            .send()
+            .map_ok(|(_, x)| stream::iter(x.0.into_iter().map(Ok)))
+            .try_flatten_stream()
+            // an update for a full queue holds back the next ones until its handler catches up
+            .try_filter_map(move |val| self.clone().dispatch_once(guard.clone(), val).map(Ok))
+    }
+
+    /// Holds back the updates of busy chats if they are processed sequentially
+    ///
+    /// Returns the receiver of chats which finished an update, which must be passed to
+    /// `resume_chats`.
+    fn start_lanes(&mut self) -> Option<UnboundedReceiver<objects::Integer>> {
+        if self.processing != Processing::SequentialPerChat {
+            return None;
+        }
+
+        let (lanes, released) = Lanes::new();
+        self.lanes = Some(lanes);
+
+        Some(released)
+    }
+
+    /// Dispatches the next update of every released chat
+    ///
+    /// After a shutdown the held back updates are dispatched at once, so that none is lost.
+    fn resume_chats(
+        &self,
+        released: Option<UnboundedReceiver<objects::Integer>>
+    ) -> impl Stream<Item = Result<(RequestHandle, objects::Update), Error>> {
+        let released = match released {
+            Some(released) => released,
+            None => return stream::empty().left_stream(),
+        };
+
+        let bot = self.clone();
+        let shutdown = self.shutdown.wait();
+
+        let resumed = released
+            .filter_map(move |chat| {
+                let next = bot.lanes.as_ref().and_then(|lanes| lanes.next(chat));
+                let bot = bot.clone();
+
+                async move {
+                    let (val, turn) = next?;
+
+                    bot.dispatch_turn(val, Some(turn)).await
+                }
+            });
+
+        let bot = self.clone();
+
+        let remaining = stream::iter(Some(()))
+            .map(move |_| {
+                let remaining = bot.lanes.as_ref().map(|lanes| lanes.drain()).unwrap_or_default();
+
+                if !remaining.is_empty() {
+                    info!("Dispatching {} held back updates after the shutdown", remaining.len());
+                }
+
+                let bot = bot.clone();
+
+                stream::iter(remaining).filter_map(move |val| {
+                    let bot = bot.clone();
+
+                    async move { bot.dispatch_turn(val, None).await }
+                })
+            })
+            .flatten();
+
+        resumed
+            .take_until(shutdown)
+            .chain(remaining)
+            .map(Ok)
+            .right_stream()
+    }
+
+    /// Creates the guard against repeated updates with the offset of the store, if there is one
+    ///
+    /// The store is read on the blocking thread pool.
+    pub async fn load_offset(&self) -> UpdateGuard {
+        let loaded = match self.offset_store.clone() {
+            Some(store) => tokio::task::spawn_blocking(move || store.load()).await
+                .unwrap_or_else(|err| Err(Error::from(err.context(ErrorKind::Tokio)))),
+            None => Ok(None),
+        };
+
+        let offset = match loaded {
+            Ok(offset) => offset.unwrap_or(0),
+            Err(err) => {
+                warn!("Could not load the update offset: {}", err);
+
+                0
+            }
+        };
+
+        UpdateGuard::new(offset)
+    }
+
+    /// The offset up to which updates are confirmed with Telegram and saved in the store
+    ///
+    /// It stays before the oldest held back update, so that Telegram keeps this update until it
+    /// was dispatched. Updates which are delivered again in the meantime are dropped by the guard.
+    fn confirmed_offset(&self, guard: &UpdateGuard) -> objects::Integer {
+        let held_back = self.lanes.as_ref().and_then(|lanes| lanes.oldest());
+
+        held_back.map_or(guard.offset(), |id| id.min(guard.offset()))
+    }
+
+    /// Dispatches an update unless it was handled before and saves the new offset afterwards
+    ///
+    /// The store is written on the blocking thread pool.
+    fn dispatch_once(self, guard: UpdateGuard, val: objects::Update) -> BoxFuture<'static, Option<(RequestHandle, objects::Update)>> {
+        if !guard.check(val.update_id) {
+            debug!("Dropped the already handled update {}", val.update_id);
+
+            return future::ready(None).boxed();
+        }
+
+        async move {
+            let res = self.dispatch(val).await;
+
+            if let Some(store) = self.offset_store.clone() {
+                let offset = self.confirmed_offset(&guard);
+
+                let saved = tokio::task::spawn_blocking(move || store.save(offset)).await
+                    .unwrap_or_else(|err| Err(Error::from(err.context(ErrorKind::Tokio))));
+
+                if let Err(err) = saved {
+                    warn!("Could not save the update offset: {}", err);
+                }
+            }
+
+            res
+        }.boxed()
+    }
+
+    /// Returns the request handle for an update, with its session if a session store is set
+    async fn request_for(&self, update: &objects::Update) -> RequestHandle {
+        let mut request = self.request.clone();
+
+        let (store, key) = match (self.session_store.as_ref(), SessionKey::of(update, self.session_scope)) {
+            (Some(store), Some(key)) => (store.clone(), key),
+            _ => return request,
+        };
+
+        let session = tokio::task::spawn_blocking(move || Session::load_from(key, store)).await
+            .unwrap_or_else(|err| Err(Error::from(err.context(ErrorKind::Tokio))));
+
+        match session {
+            Ok(session) => request.session = Some(session),
+            Err(err) => warn!("Could not load the session {}: {}", key, err),
+        }
+
+        request
+    }
+
+    /// Forwards an update to the registered handlers
+    ///
+    /// If a session store is set, the session of the update is loaded first and attached to the
+    /// request handle which is passed to the handlers. Then the update passes the middlewares,
+    /// which may stop it.
+    ///
+    /// Messages and callback queries of active conversations are sent to their dialogue first.
+    /// Callback queries, inline queries, chosen inline results, edited messages and channel posts
+    /// are sent to their handlers. A message goes to the handler of its command, if the guard of
+    /// the command allows it, then to the first matching route and finally to the unknown handler,
+    /// if it is a command, or to the first matching `messages` stream. The update is returned if
+    /// nobody handled it.
+    ///
+    /// If the updates of a chat are processed sequentially and its previous update is still
+    /// handled, the update is held back and dispatched later by the update loop.
+    pub fn dispatch(&self, val: objects::Update) -> BoxFuture<'_, Option<(RequestHandle, objects::Update)>> {
+        debug!("Got an update from Telegram: {:?}", val);
+
+        let (val, turn) = match (self.lanes.as_ref(), queue::chat_of(&val)) {
+            (Some(lanes), Some(chat)) => match lanes.enter(chat, val) {
+                Some((val, turn)) => (val, Some(turn)),
+                None => {
+                    debug!("Held back an update of the busy chat {}", chat);
+
+                    return future::ready(None).boxed();
+                }
+            },
+            _ => (val, None),
+        };
+
+        // boxed, because a large update would otherwise be kept on the stack of every future
+        // which awaits it
+        self.dispatch_turn(val, turn).boxed()
+    }
+
+    /// Forwards an update which has the turn of its chat, if processed sequentially
+    async fn dispatch_turn(&self, mut val: objects::Update, turn: Option<ChatTurn>) -> Option<(RequestHandle, objects::Update)> {
+        let mut request = self.request_for(&val).await;
+        request.turn = turn.map(Arc::new);
+
+        if let Some(migration) = val.message.as_ref().and_then(ChatMigration::from_message) {
+            self.request.notify_migration(migration).await;
+        }
+
+        let mut annotations = Annotations::default();
+
+        for middleware in &self.request.middlewares {
+            let mut ctx = UpdateContext {
+                update: &mut val,
+                request: &request,
+                annotations: &mut annotations,
+            };
+
+            if middleware.on_update(&mut ctx) == Flow::Stop {
+                debug!("A middleware stopped the update {}", val.update_id);
+
+                return None;
+            }
+        }
+
+        request.annotations = annotations;
+
+        // a guarded command starts or cancels a conversation only if the guard allows it
+        let guarded = val.message.as_ref().and_then(|msg| {
+            let guard = self.guards.get(&command::command_of(msg, self.name.as_deref())?)?;
+            let dialogue = self.dialogues.iter().find(|x| x.accepts(msg, self.name.as_deref()))?;
+
+            Some((guard.clone(), dialogue.clone()))
+        });
+
+        if let Some((guard, dialogue)) = guarded {
+            let name = self.name.clone();
+            let message = val.message.take().unwrap();
+
+            self.guard_command(&guard, request, message, Box::new(move |bot, message| {
+                async move { dialogue.deliver(&bot, message, name.as_deref()).await }.boxed()
+            })).await;
+
+            return None;
+        }
+
+        for dialogue in &self.dialogues {
+            if dialogue.route(&request, &mut val, self.name.as_deref()).await {
+                return None;
+            }
+        }
+
+        if let Some(callback_query) = val.callback_query.take() {
+            let data = callback_query.data.as_deref().unwrap_or("");
+
+            let sender = self.callback_routes.iter()
+                .find(|(prefix, _)| data.starts_with(prefix.as_str()))
+                .map(|(_, sender)| sender)
+                .or(self.callback_handler.as_ref());
+
+            match sender {
+                Some(sender) => {
+                    sender
+                        .send_when_room((request.clone(), callback_query))
+                        .await
+                        .unwrap_or_else(|e| error!("Error: {}", e));
+                    return None;
+                }
+                None => val.callback_query = Some(callback_query),
+            }
+        }
+
+        if let Some(sender) = self.inline_handler.clone() {
+            if let Some(inline_query) = val.inline_query.take() {
+                sender
+                    .send_when_room((request.clone(), inline_query))
+                    .await
+                    .unwrap_or_else(|e| error!("Error: {}", e));
+                return None;
+            }
+        }
+
+        if let Some(sender) = self.chosen_inline_handler.clone() {
+            if let Some(chosen_inline_result) = val.chosen_inline_result.take() {
+                sender
+                    .send_when_room((request.clone(), chosen_inline_result))
+                    .await
+                    .unwrap_or_else(|e| error!("Error: {}", e));
+                return None;
+            }
+        }
+
+        let posts = [
+            (&self.edited_message_handler, &mut val.edited_message),
+            (&self.channel_post_handler, &mut val.channel_post),
+            (&self.edited_channel_post_handler, &mut val.edited_channel_post),
+        ];
+
+        for (handler, post) in posts {
+            if let Some(sender) = handler.clone() {
+                if let Some(message) = post.take() {
+                    sender
+                        .send_when_room((request.clone(), message))
+                        .await
+                        .unwrap_or_else(|e| error!("Error: {}", e));
+                    return None;
+                }
+            }
+        }
+
+        let mut sndr: Option<HandlerSender<(RequestHandle, objects::Message)>> = None;
+
+        if let Some(ref mut message) = val.message {
+            let is_command = message.entities.as_ref().and_then(|x| x.first()).map(|x| x.kind == "bot_command");
+
+            if let Some(true) = is_command {
+                if let Some(text) = message.text.clone() {
+                    let mut content = text.split_whitespace();
+                    if let Some(mut cmd) = content.next() {
+                        if let Some(name) = self.name.as_ref() {
+                            if cmd.ends_with(name.as_str()) {
+                                cmd = cmd.rsplit_once('@').map(|x| x.0).unwrap();
+                            }
+                        }
+                        let deliver: Option<Deliver> = if let Some(sender) = self.handlers.get(cmd) {
+                            let sender = sender.clone();
+                            let rest = content.collect::<Vec<&str>>().join(" ");
+
+                            Some(Box::new(move |bot, mut message| {
+                                message.text = Some(rest);
+
+                                async move {
+                                    sender
+                                        .send_when_room((bot, message))
+                                        .await
+                                        .unwrap_or_else(|e| error!("Error: {}", e));
+                                }.boxed()
+                            }))
+                        } else if let Some((_, sink)) = self.command_sets.iter().find(|(names, _)| names.contains(&cmd)) {
+                            let sink = sink.clone();
+                            let name = cmd.to_string();
+
+                            // quoted arguments need the original whitespace
+                            let text = text.trim_start();
+                            let args = text[text.find(char::is_whitespace).unwrap_or(text.len())..].to_string();
+
+                            Some(Box::new(move |bot, mut message| {
+                                message.text = Some(args.trim().into());
+
+                                sink(bot, message, &name, &args)
+                            }))
+                        } else {
+                            None
+                        };
+
+                        if let Some(deliver) = deliver {
+                            let message = val.message.take().unwrap();
+
+                            match self.guards.get(cmd) {
+                                Some(guard) => self.guard_command(guard, request.clone(), message, deliver).await,
+                                None => deliver(request.clone(), message).await,
+                            }
+
+                            return None;
+                        } else if let Some(sender) = self.help_handler.as_ref().filter(|_| cmd == "/help") {
+                            sndr = Some(sender.clone());
+                        }
+                    }
+                }
+            }
+
+            if sndr.is_none() {
+                sndr = self.routes.iter()
+                    .find(|(route, _)| route.matches(message))
+                    .map(|(_, sender)| sender.clone());
+            }
+
+            if sndr.is_none() {
+                sndr = match is_command {
+                    Some(true) => self.unknown_handler.clone(),
+                    _ => self.message_handlers.iter()
+                        .find(|(filter, _)| filter.matches(message))
+                        .map(|(_, sender)| sender.clone()),
+                };
+            }
+        }
+
+        if let Some(sender) = sndr {
+            sender
+                .send_when_room((request.clone(), val.message.unwrap()))
+                .await
+                .unwrap_or_else(|e| error!("Error: {}", e));
+            None
+        } else {
+            Some((request.clone(), val))
+        }
+    }
+
+    ///
+    /// The main update loop, the update function is called every update_interval milliseconds
+    /// When an update is available the offset will be updated and the message is filtered
+    /// for commands
+    /// The message is forwarded to the returned stream if no command was found
+    /// After a shutdown the stream confirms the last offset with Telegram and ends
+    pub fn get_stream(
+        mut self,
+        name: Option<String>
+    ) -> impl Stream<Item = Result<(RequestHandle, objects::Update), Error>> {
+        self.name = name;
+
+        stream::once(async move {
+            let guard = self.load_offset().await;
+
+            self.poll_updates(guard).boxed()
+        }).flatten()
+    }
+
+    /// Polls the updates after the offset of `guard` until a shutdown
+    fn poll_updates(mut self, guard: UpdateGuard) -> impl Stream<Item = Result<(RequestHandle, objects::Update), Error>> {
+        let duration = Duration::from_millis(self.update_interval);
+        let ticks = stream::unfold(time::interval(duration), |mut interval| async move {
+            interval.tick().await;
+
+            Some(((), interval))
+        });
+
+        let shutdown = self.shutdown.wait();
+
+        let released = self.start_lanes();
+        let resumed = self.resume_chats(released);
+
+        let bot = self.clone();
+        let offset = guard.clone();
+
+        // runs once the loop is stopped, the pending getUpdates request is cancelled before
+        let commit = stream::once(async move {
+            bot.commit_offset(bot.confirmed_offset(&offset)).await
+        });
+
+        let updates = ticks
+            .map(move |_| self.clone().process_updates(guard.clone()).boxed())
+            .flatten()
+            .take_until(shutdown);
+
+        stream::select(updates, resumed)
+            .chain(commit.try_filter_map(|_| future::ok(None)))
+    }
+
+    /// Receives updates from the local webhook listener instead of polling the Telegram server
+    ///
+    /// Every update is dispatched to the registered handlers like in `get_stream`, unhandled
+    /// updates are forwarded to the returned stream. The listener is closed after a shutdown.
+    pub fn get_webhook_stream(
+        mut self,
+        name: Option<String>,
+        webhook: Webhook
+    ) -> impl Stream<Item = Result<(RequestHandle, objects::Update), Error>> {
+        self.name = name;
+
+        stream::once(async move {
+            let guard = self.load_offset().await;
+
+            self.receive_updates(guard, webhook).boxed()
+        }).flatten()
+    }
+
+    /// Dispatches the updates of the webhook listener until a shutdown
+    fn receive_updates(mut self, guard: UpdateGuard, webhook: Webhook) -> impl Stream<Item = Result<(RequestHandle, objects::Update), Error>> {
+        let shutdown = self.shutdown.wait();
+
+        let released = self.start_lanes();
+        let resumed = self.resume_chats(released);
+
+        let updates = webhook.listen()
+            .try_filter_map(move |val| self.clone().dispatch_once(guard.clone(), val).map(Ok))
+            .take_until(shutdown);
+
+        stream::select(updates, resumed)
+    }
+
+    /// Resolves the name of the bot and dispatches all updates to the registered handlers
+    ///
+    /// The returned future runs on the runtime it is spawned on and ends with an error or after a
+    /// shutdown.
+    pub fn into_future(&self) -> impl Future<Output = Result<(), Error>> {
+        let mut bot = self.clone();
+
+        // unhandled updates are dropped, so only the registered types are requested
+        bot.catch_all = false;
+
+        async move {
+            let name = bot.resolve_name().await?;
+
+            if bot.sync_commands && !bot.registry.is_empty() {
+                if let Err(err) = bot.publish_commands().await {
+                    warn!("Could not publish the commands: {}", err);
+                }
+            }
+
+            let background = future::join3(bot.serve_help(), bot.serve_guards(), bot.expire_dialogues());
+
+            let updates = async move {
+                match bot.webhook.clone() {
+                    Some(webhook) => bot.get_webhook_stream(name, webhook)
+                        .try_for_each(|_| future::ok(()))
+                        .await,
+                    None => {
+                        bot.prepare_polling().await?;
+
+                        bot.get_stream(name)
+                            .try_for_each(|_| future::ok(()))
+                            .await
+                    }
+                }
+            };
+
+            futures::pin_mut!(background, updates);
+
+            // the help is answered, permissions are checked and conversations time out as long as
+            // updates are received
+            match future::select(updates, background).await {
+                Either::Left((res, _)) => res,
+                Either::Right((_, updates)) => updates.await,
+            }
+        }
+    }
+
+    /// Runs the bot together with the handlers in `other` on the current runtime
+    ///
+    /// After a shutdown the update loop stops and the bot drops its handler channels, so that the
+    /// handler streams end once they processed all received messages. `other` gets the shutdown
+    /// timeout to finish, afterwards this function returns. Clones of the bot which are kept
+    /// elsewhere hold the channels open, the timeout applies nonetheless.
+    ///
+    /// ```no_run
+    /// use telebot::Bot;
+    /// use telebot::functions::*;
+    /// use futures::{future, TryStreamExt};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut bot = Bot::new("TOKEN");
+    ///     let shutdown = bot.shutdown_handle();
+    ///
+    ///     let handle = bot.new_cmd("/ping")
+    ///         .and_then(|(bot, msg)| bot.message(msg.chat.id, "pong".into()).send())
+    ///         .try_for_each(|_| future::ok(()));
+    ///
+    ///     tokio::spawn(async move {
+    ///         tokio::time::sleep(std::time::Duration::from_secs(60)).await;
+    ///         shutdown.shutdown();
+    ///     });
+    ///
+    ///     bot.run_with(handle).await;
+    /// }
+    /// ```
+    pub async fn run_with<I, T>(self, other: I)
+    where
+        I: Future<Output = Result<T, Error>>
+    {
+        let timeout = self.shutdown_timeout;
+        let updates = self.into_future();
+
+        // the update loop holds its own clone, the handler channels are closed when it ends
+        drop(self);
+
+        futures::pin_mut!(updates, other);
+
+        let result = match future::select(updates, other).await {
+            Either::Left((Ok(()), other)) => match time::timeout(timeout, other).await {
+                Ok(res) => res.map(|_| ()),
+                Err(_) => {
+                    warn!("The handlers did not finish within {:?} after the shutdown", timeout);
+
+                    Ok(())
+                }
+            },
+            Either::Right((Ok(_), updates)) => updates.await,
+            Either::Left((Err(e), _)) | Either::Right((Err(e), _)) => Err(e),
+        };
+
+        if let Err(e) = result {
+            eprintln!("Error: could not resolve the bot name!");
+
+            for (i, cause) in e.iter_causes().enumerate() {
+                println!(" => {}: {}", i, cause);
+            }
+        }
     }
 
-    pub fn run(self) {
-        self.run_with(Ok(()));
+    pub async fn run(self) {
+        self.run_with(future::ok::<(), Error>(())).await;
     }
 }
@@ -0,0 +1,401 @@
+//! Typed commands which are parsed from the text of a message
+//!
+//! Deriving `BotCommands` for an enum turns every variant into a command. The name of the
+//! command is the variant name in snake case, it can be changed with `#[command = "name"]`. The
+//! fields of a variant are parsed from the arguments in order: numbers, single words, quoted
+//! strings with spaces, optional arguments and the rest of the line. `#[description = ".."]`,
+//! `#[scope = "private"]` (`all`, `private`, `groups` or `admins`) and `#[hidden]` describe the
+//! command for the command menu and /help.
+//!
+//! ```
+//! use telebot::command::{BotCommands, Rest};
+//!
+//! #[derive(BotCommands, Debug, PartialEq)]
+//! enum Command {
+//!     #[description = "Starts the bot"]
+//!     Start,
+//!     #[description = "Sends a map of the location"]
+//!     Location { lat: f32, long: f32 },
+//!     #[command = "say"]
+//!     Echo(Rest),
+//!     #[scope = "private"]
+//!     #[hidden]
+//!     Remind { minutes: u32, text: String, note: Option<String> },
+//! }
+//!
+//! fn main() {
+//!     assert_eq!(
+//!         Command::from_text("/location 2.3 12.3", None).unwrap(),
+//!         Command::Location { lat: 2.3, long: 12.3 }
+//!     );
+//!     assert_eq!(
+//!         Command::from_text("/remind 5 \"buy milk\"", None).unwrap(),
+//!         Command::Remind { minutes: 5, text: "buy milk".into(), note: None }
+//!     );
+//!
+//!     let info = &Command::descriptions()[3];
+//!     assert_eq!(info.name, "/remind");
+//!     assert!(info.hidden);
+//!
+//!     let err = Command::from_text("/location 2.3 north", None).unwrap_err();
+//!     assert_eq!(
+//!         err.to_string(),
+//!         "\"north\" is not a valid <long>, expected a number\nUsage: /location <lat> <long>"
+//!     );
+//! }
+//! ```
+
+use crate::bot::RequestHandle;
+use crate::objects::{BotCommandScope, Message};
+
+use std::{fmt, str::FromStr, sync::Arc};
+
+use failure::Fail;
+use futures::future::BoxFuture;
+
+pub use telebot_derive::BotCommands;
+
+/// A set of commands which can be parsed from a message
+///
+/// Usually derived with `#[derive(BotCommands)]`.
+pub trait BotCommands: Sized {
+    /// The names of all commands including the leading slash
+    fn names() -> Vec<&'static str>;
+
+    /// Parses the arguments of the command `name`
+    fn parse(name: &str, args: &str) -> Result<Self, CommandError>;
+
+    /// Describes all commands for the command menu and /help
+    fn descriptions() -> Vec<CommandInfo> {
+        Self::names().into_iter().map(|name| CommandInfo::new(name, "")).collect()
+    }
+
+    /// Parses a complete message text like `/cmd@bot_name arg1 arg2`
+    fn from_text(text: &str, bot_name: Option<&str>) -> Result<Self, CommandError> {
+        let text = text.trim_start();
+        let end = text.find(char::is_whitespace).unwrap_or(text.len());
+        let (mut name, args) = text.split_at(end);
+
+        if let Some((cmd, target)) = name.rsplit_once('@') {
+            if bot_name.map(|x| x.trim_start_matches('@') == target).unwrap_or(true) {
+                name = cmd;
+            }
+        }
+
+        Self::parse(name, args)
+    }
+}
+
+/// The chats in which a command is offered
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CommandScope {
+    /// Every chat
+    All,
+    /// Private chats with the bot
+    Private,
+    /// Groups and supergroups
+    Groups,
+    /// Administrators of groups and supergroups
+    Admins,
+}
+
+impl CommandScope {
+    /// The scope of the command menu
+    pub fn to_scope(self) -> BotCommandScope {
+        match self {
+            CommandScope::All => BotCommandScope::Default,
+            CommandScope::Private => BotCommandScope::AllPrivateChats,
+            CommandScope::Groups => BotCommandScope::AllGroupChats,
+            CommandScope::Admins => BotCommandScope::AllChatAdministrators,
+        }
+    }
+
+    /// Checks whether a command of this scope is offered to users of `other`
+    ///
+    /// Telegram shows only the commands of the most specific scope, so the menu of a scope has to
+    /// include the commands of all broader scopes, e.g. administrators see group commands too.
+    pub fn includes(self, other: CommandScope) -> bool {
+        match (self, other) {
+            (CommandScope::All, _) => true,
+            (CommandScope::Groups, CommandScope::Admins) => true,
+            (a, b) => a == b,
+        }
+    }
+}
+
+/// A command with its description for the command menu and /help
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommandInfo {
+    /// The name including the leading slash
+    pub name: String,
+    pub description: String,
+    pub scope: CommandScope,
+    /// Hidden commands work, but are neither in the menu nor in /help
+    pub hidden: bool,
+}
+
+impl CommandInfo {
+    pub fn new(name: &str, description: &str) -> CommandInfo {
+        CommandInfo {
+            name: normalize(name),
+            description: description.into(),
+            scope: CommandScope::All,
+            hidden: false,
+        }
+    }
+
+    /// Offers the command only in chats of this scope
+    pub fn scope(mut self, scope: CommandScope) -> CommandInfo {
+        self.scope = scope;
+
+        self
+    }
+
+    /// Hides the command from the menu and /help
+    pub fn hidden(mut self, hidden: bool) -> CommandInfo {
+        self.hidden = hidden;
+
+        self
+    }
+}
+
+/// The remaining text of the arguments, unparsed and with surrounding whitespace removed
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Rest(pub String);
+
+impl fmt::Display for Rest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// The reason why parsing a command failed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CommandErrorKind {
+    /// The command is not part of the set
+    UnknownCommand(String),
+    /// A required argument is missing
+    MissingArgument(String),
+    /// An argument could not be parsed
+    InvalidArgument {
+        name: String,
+        value: String,
+        expected: &'static str,
+    },
+    /// There are more arguments than the command accepts
+    TooManyArguments(String),
+    /// A quoted argument has no closing quote
+    UnclosedQuote,
+}
+
+/// A parse error with a message which can be shown to the user
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommandError {
+    pub kind: CommandErrorKind,
+    pub usage: Option<String>,
+}
+
+impl CommandError {
+    pub fn new(kind: CommandErrorKind) -> CommandError {
+        CommandError { kind, usage: None }
+    }
+
+    /// Adds a usage line like `/location <lat> <long>` to the message
+    pub fn with_usage(mut self, usage: String) -> CommandError {
+        self.usage = Some(usage);
+
+        self
+    }
+}
+
+impl Fail for CommandError {}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            CommandErrorKind::UnknownCommand(ref name) => write!(f, "Unknown command {}", name)?,
+            CommandErrorKind::MissingArgument(ref name) => write!(f, "Missing argument <{}>", name)?,
+            CommandErrorKind::InvalidArgument { ref name, ref value, expected } => {
+                write!(f, "\"{}\" is not a valid <{}>, expected {}", value, name, expected)?
+            }
+            CommandErrorKind::TooManyArguments(ref rest) => write!(f, "Unexpected argument \"{}\"", rest)?,
+            CommandErrorKind::UnclosedQuote => write!(f, "Missing closing quote")?,
+        }
+
+        if let Some(ref usage) = self.usage {
+            write!(f, "\nUsage: {}", usage)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Splits the arguments of a command into words and quoted strings
+///
+/// Inside of quotes `\"` and `\\` are unescaped.
+pub struct ArgParser<'a> {
+    rest: &'a str,
+}
+
+impl<'a> ArgParser<'a> {
+    pub fn new(args: &'a str) -> ArgParser<'a> {
+        ArgParser { rest: args.trim_start() }
+    }
+
+    /// Checks whether all arguments were consumed
+    pub fn is_empty(&self) -> bool {
+        self.rest.is_empty()
+    }
+
+    /// Returns the next word or quoted string
+    pub fn next_word(&mut self) -> Option<Result<String, CommandError>> {
+        if self.rest.is_empty() {
+            return None;
+        }
+
+        if !self.rest.starts_with('"') {
+            let end = self.rest.find(char::is_whitespace).unwrap_or(self.rest.len());
+            let (word, rest) = self.rest.split_at(end);
+            self.rest = rest.trim_start();
+
+            return Some(Ok(word.into()));
+        }
+
+        let mut word = String::new();
+        let mut chars = self.rest.char_indices().skip(1);
+
+        while let Some((pos, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.rest = self.rest[pos + 1..].trim_start();
+
+                    return Some(Ok(word));
+                }
+                '\\' => match chars.next() {
+                    Some((_, c)) => word.push(c),
+                    None => break,
+                },
+                c => word.push(c),
+            }
+        }
+
+        Some(Err(CommandError::new(CommandErrorKind::UnclosedQuote)))
+    }
+
+    /// Consumes the remaining text
+    pub fn rest(&mut self) -> String {
+        let rest = self.rest.trim_end().into();
+        self.rest = "";
+
+        rest
+    }
+
+    /// Parses the next argument
+    pub fn arg<T: CommandArg>(&mut self, name: &str) -> Result<T, CommandError> {
+        T::parse_arg(self, name)
+    }
+
+    /// Fails if there are arguments left
+    pub fn finish(mut self) -> Result<(), CommandError> {
+        match self.is_empty() {
+            true => Ok(()),
+            false => Err(CommandError::new(CommandErrorKind::TooManyArguments(self.rest()))),
+        }
+    }
+}
+
+/// A type which can be parsed from the arguments of a command
+pub trait CommandArg: Sized {
+    /// Parses the argument `name` and consumes its text
+    fn parse_arg(args: &mut ArgParser, name: &str) -> Result<Self, CommandError>;
+
+    /// Describes the argument in the usage line
+    fn usage(name: &str) -> String {
+        format!("<{}>", name)
+    }
+}
+
+/// Parses the next word with `FromStr`
+fn parse_word<T: FromStr>(args: &mut ArgParser, name: &str, expected: &'static str) -> Result<T, CommandError> {
+    let word = args.next_word()
+        .unwrap_or_else(|| Err(CommandError::new(CommandErrorKind::MissingArgument(name.into()))))?;
+
+    word.parse().map_err(|_| CommandError::new(CommandErrorKind::InvalidArgument {
+        name: name.into(),
+        value: word,
+        expected,
+    }))
+}
+
+macro_rules! impl_command_arg {
+    ($expected:expr, $($ty:ty),*) => {
+        $(
+            impl CommandArg for $ty {
+                fn parse_arg(args: &mut ArgParser, name: &str) -> Result<Self, CommandError> {
+                    parse_word(args, name, $expected)
+                }
+            }
+        )*
+    }
+}
+
+impl_command_arg!("a whole number", i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+impl_command_arg!("a number", f32, f64);
+impl_command_arg!("true or false", bool);
+impl_command_arg!("a single character", char);
+impl_command_arg!("a word or a quoted text", String);
+
+impl<T: CommandArg> CommandArg for Option<T> {
+    fn parse_arg(args: &mut ArgParser, name: &str) -> Result<Self, CommandError> {
+        match args.is_empty() {
+            true => Ok(None),
+            false => T::parse_arg(args, name).map(Some),
+        }
+    }
+
+    fn usage(name: &str) -> String {
+        format!("[{}]", name)
+    }
+}
+
+impl CommandArg for Rest {
+    fn parse_arg(args: &mut ArgParser, _name: &str) -> Result<Self, CommandError> {
+        Ok(Rest(args.rest()))
+    }
+
+    fn usage(name: &str) -> String {
+        format!("<{}...>", name)
+    }
+}
+
+/// Adds the leading slash to a command name
+pub(crate) fn normalize(cmd: &str) -> String {
+    if cmd.starts_with('/') {
+        cmd.into()
+    } else {
+        format!("/{}", cmd)
+    }
+}
+
+/// Returns the command of a message without the name of the bot
+pub(crate) fn command_of(msg: &Message, bot_name: Option<&str>) -> Option<String> {
+    let is_command = msg.entities.as_ref()
+        .and_then(|x| x.first())
+        .map(|x| x.kind == "bot_command")
+        .unwrap_or(false);
+
+    if !is_command {
+        return None;
+    }
+
+    let cmd = msg.text.as_ref()?.split_whitespace().next()?;
+
+    match bot_name {
+        Some(name) if cmd.ends_with(name) => cmd.rsplit_once('@').map(|x| x.0.to_string()),
+        _ => Some(cmd.into()),
+    }
+}
+
+/// Forwards a command with its arguments to a typed command stream, the future waits for room in
+/// the queue
+pub(crate) type CommandSink = Arc<dyn Fn(RequestHandle, Message, &str, &str) -> BoxFuture<'static, ()> + Send + Sync>;
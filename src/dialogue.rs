@@ -0,0 +1,465 @@
+//! Multi-step conversations which remember a state for every chat or user
+//!
+//! A `Dialogue` stores the current state of each conversation. While a conversation is active,
+//! its messages and callback queries are sent to the dialogue stream together with the state,
+//! instead of the other handlers. Commands are still routed as usual, except for the cancel
+//! commands which end the conversation. Conversations without input for the timeout are ended as
+//! well.
+//!
+//! ```no_run
+//! use telebot::Bot;
+//! use telebot::dialogue::{Dialogue, DialogueInput};
+//! use telebot::functions::*;
+//! use futures::{future, TryStreamExt};
+//! use std::time::Duration;
+//!
+//! #[derive(Clone)]
+//! enum SignUp {
+//!     AskName,
+//!     AskAge { name: String },
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let mut bot = Bot::new("TOKEN");
+//!
+//! let dialogue = Dialogue::new()
+//!     .entry("/signup", SignUp::AskName)
+//!     .timeout(Duration::from_secs(300));
+//!
+//! let handle = bot.dialogue(dialogue)
+//!     .and_then(|(bot, update)| async move {
+//!         let text = match (update.state.clone(), update.input) {
+//!             (SignUp::AskName, DialogueInput::Start(_)) => "What's your name?".to_string(),
+//!             (SignUp::AskName, DialogueInput::Message(msg)) => {
+//!                 update.handle.set(update.key, SignUp::AskAge { name: msg.text.unwrap_or_default() });
+//!                 "How old are you?".into()
+//!             }
+//!             (SignUp::AskAge { name }, DialogueInput::Message(msg)) => {
+//!                 update.handle.exit(update.key);
+//!                 format!("Welcome {}, age {}", name, msg.text.unwrap_or_default())
+//!             }
+//!             (_, DialogueInput::Cancelled) => "Cancelled".into(),
+//!             (_, DialogueInput::TimedOut) => "Too slow, please start again".into(),
+//!             _ => return Ok(()),
+//!         };
+//!
+//!         bot.message(update.chat_id, text).send().await.map(|_| ())
+//!     })
+//!     .try_for_each(|_| future::ok(()));
+//!
+//! bot.run_with(handle).await;
+//! # }
+//! ```
+
+use crate::bot::RequestHandle;
+use crate::command;
+use crate::objects::{CallbackQuery, Integer, Message, Update};
+use crate::queue::HandlerSender;
+
+use std::{collections::HashMap, sync::{Arc, Mutex}, time::{Duration, Instant}};
+
+use futures::{future::BoxFuture, FutureExt};
+
+/// Whether a conversation belongs to a chat or to a user
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DialogueScope {
+    /// All members of a chat share the conversation
+    Chat,
+    /// Each user has its own conversation, across all chats
+    User,
+}
+
+/// Identifies a conversation, the chat or user id depending on the scope
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DialogueKey(pub Integer);
+
+/// The input which is passed to the dialogue stream
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug)]
+pub enum DialogueInput {
+    /// The entry command which started the conversation
+    Start(Message),
+    /// A message of an active conversation
+    Message(Message),
+    /// A callback query of an active conversation
+    Callback(CallbackQuery),
+    /// The conversation was ended with a cancel command
+    Cancelled,
+    /// The conversation was ended because of the timeout
+    TimedOut,
+}
+
+/// An input together with the state of its conversation
+#[derive(Debug)]
+pub struct DialogueUpdate<S> {
+    pub key: DialogueKey,
+    /// The chat in which the conversation was seen last
+    pub chat_id: Integer,
+    /// The state when the input was received
+    pub state: S,
+    pub input: DialogueInput,
+    /// Changes the state of the conversation
+    pub handle: Dialogue<S>,
+}
+
+struct Entry<S> {
+    state: S,
+    chat_id: Integer,
+    last_seen: Instant,
+}
+
+#[derive(Clone)]
+struct Config<S> {
+    scope: DialogueScope,
+    timeout: Option<Duration>,
+    entries: Vec<(String, S)>,
+    cancel: Vec<String>,
+}
+
+/// The states of all conversations
+///
+/// The dialogue is shared between all clones, so that a conversation can be started or changed
+/// from any handler. Configuring a clone changes only the settings of this clone.
+pub struct Dialogue<S> {
+    config: Arc<Config<S>>,
+    states: Arc<Mutex<HashMap<DialogueKey, Entry<S>>>>,
+}
+
+impl<S> Clone for Dialogue<S> {
+    fn clone(&self) -> Dialogue<S> {
+        Dialogue {
+            config: self.config.clone(),
+            states: self.states.clone(),
+        }
+    }
+}
+
+impl<S: std::fmt::Debug> std::fmt::Debug for Dialogue<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Dialogue")
+            .field("scope", &self.config.scope)
+            .field("active", &self.states.lock().unwrap().len())
+            .finish()
+    }
+}
+
+impl<S: Clone + Send + Sync + 'static> Default for Dialogue<S> {
+    fn default() -> Dialogue<S> {
+        Dialogue::new()
+    }
+}
+
+impl<S: Clone + Send + Sync + 'static> Dialogue<S> {
+    /// Creates a dialogue for every chat, which can be cancelled with /cancel and never times
+    /// out
+    pub fn new() -> Dialogue<S> {
+        Dialogue {
+            config: Arc::new(Config {
+                scope: DialogueScope::Chat,
+                timeout: None,
+                entries: Vec::new(),
+                cancel: vec!["/cancel".into()],
+            }),
+            states: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    fn configure<F: FnOnce(&mut Config<S>)>(mut self, f: F) -> Dialogue<S> {
+        f(Arc::make_mut(&mut self.config));
+
+        self
+    }
+
+    /// Keeps a conversation per chat or per user
+    pub fn scope(self, scope: DialogueScope) -> Dialogue<S> {
+        self.configure(|config| config.scope = scope)
+    }
+
+    /// Ends conversations without input for this duration
+    pub fn timeout(self, timeout: Duration) -> Dialogue<S> {
+        self.configure(|config| config.timeout = Some(timeout))
+    }
+
+    /// Starts a conversation in `state` when `cmd` is sent outside of a conversation
+    ///
+    /// The command message is passed to the stream as `DialogueInput::Start` with the initial
+    /// state.
+    pub fn entry(self, cmd: &str, state: S) -> Dialogue<S> {
+        let cmd = command::normalize(cmd);
+
+        self.configure(|config| config.entries.push((cmd, state)))
+    }
+
+    /// Sets the commands which end a conversation, the default is /cancel
+    pub fn cancel_cmds(self, cmds: &[&str]) -> Dialogue<S> {
+        let cmds = cmds.iter().map(|x| command::normalize(x)).collect();
+
+        self.configure(|config| config.cancel = cmds)
+    }
+
+    /// The key of the conversation to which a message belongs
+    pub fn key_of(&self, msg: &Message) -> Option<DialogueKey> {
+        match self.config.scope {
+            DialogueScope::Chat => Some(DialogueKey(msg.chat.id)),
+            DialogueScope::User => msg.from.as_ref().map(|x| DialogueKey(x.id)),
+        }
+    }
+
+    /// The current state of a conversation
+    pub fn get(&self, key: DialogueKey) -> Option<S> {
+        self.states.lock().unwrap().get(&key).map(|x| x.state.clone())
+    }
+
+    /// Starts a conversation in `chat_id` or moves it to the next state
+    pub fn start(&self, key: DialogueKey, chat_id: Integer, state: S) {
+        self.states.lock().unwrap().insert(key, Entry {
+            state,
+            chat_id,
+            last_seen: Instant::now(),
+        });
+    }
+
+    /// Moves an active conversation to the next state
+    ///
+    /// Conversations which already ended are not restarted.
+    pub fn set(&self, key: DialogueKey, state: S) {
+        if let Some(entry) = self.states.lock().unwrap().get_mut(&key) {
+            entry.state = state;
+            entry.last_seen = Instant::now();
+        }
+    }
+
+    /// Ends a conversation and returns its last state
+    pub fn exit(&self, key: DialogueKey) -> Option<S> {
+        self.states.lock().unwrap().remove(&key).map(|x| x.state)
+    }
+
+    /// Checks whether an entry is older than the timeout
+    fn is_expired(&self, entry: &Entry<S>, now: Instant) -> bool {
+        self.config.timeout
+            .map(|timeout| now.duration_since(entry.last_seen) >= timeout)
+            .unwrap_or(false)
+    }
+}
+
+/// Forwards the updates of active conversations to a dialogue stream
+///
+/// The futures wait for room in the queue of the stream.
+pub(crate) trait DialogueRouter: Send + Sync {
+    /// Takes the message or callback query of the update if it belongs to a conversation
+    fn route<'a>(&'a self, request: &'a RequestHandle, update: &'a mut Update, bot_name: Option<&'a str>) -> BoxFuture<'a, bool>;
+
+    /// Whether a command would start or cancel a conversation
+    fn accepts(&self, msg: &Message, bot_name: Option<&str>) -> bool;
+
+    /// Passes a command which was allowed by its guard
+    fn deliver<'a>(&'a self, request: &'a RequestHandle, msg: Message, bot_name: Option<&'a str>) -> BoxFuture<'a, ()>;
+
+    /// Ends all conversations which timed out
+    fn expire<'a>(&'a self, request: &'a RequestHandle, now: Instant) -> BoxFuture<'a, ()>;
+
+    /// Whether the dialogue has a timeout
+    fn has_timeout(&self) -> bool;
+}
+
+/// What happens to a message of a conversation
+enum Step<S> {
+    /// The conversation timed out before the message arrived
+    TimedOut(Entry<S>),
+    Cancelled(S),
+    /// The message is passed to the stream, `true` if it started the conversation
+    Input(S, bool),
+    /// The message is handled as usual
+    Pass,
+}
+
+pub(crate) struct Router<S> {
+    pub dialogue: Dialogue<S>,
+    pub sender: HandlerSender<(RequestHandle, DialogueUpdate<S>)>,
+}
+
+impl<S: Clone + Send + Sync + 'static> Router<S> {
+    async fn send(&self, request: &RequestHandle, key: DialogueKey, chat_id: Integer, state: S, input: DialogueInput) {
+        let update = DialogueUpdate {
+            key,
+            chat_id,
+            state,
+            input,
+            handle: self.dialogue.clone(),
+        };
+
+        self.sender
+            .send_when_room((request.clone(), update))
+            .await
+            .unwrap_or_else(|e| error!("Error: {}", e));
+    }
+
+    /// Takes the message if it belongs to a conversation or starts one
+    async fn route_message(&self, request: &RequestHandle, slot: &mut Option<Message>, bot_name: Option<&str>) -> bool {
+        let (key, chat_id, cmd) = match slot.as_ref() {
+            Some(msg) => match self.dialogue.key_of(msg) {
+                Some(key) => (key, msg.chat.id, command::command_of(msg, bot_name)),
+                None => return false,
+            },
+            None => return false,
+        };
+
+        match self.step(key, chat_id, cmd) {
+            Step::TimedOut(entry) => {
+                self.send(request, key, entry.chat_id, entry.state, DialogueInput::TimedOut).await;
+
+                Box::pin(self.route_message(request, slot, bot_name)).await
+            }
+            Step::Cancelled(state) => {
+                slot.take();
+                self.send(request, key, chat_id, state, DialogueInput::Cancelled).await;
+
+                true
+            }
+            Step::Input(state, started) => {
+                let msg = slot.take().unwrap();
+                let input = match started {
+                    true => DialogueInput::Start(msg),
+                    false => DialogueInput::Message(msg),
+                };
+
+                self.send(request, key, chat_id, state, input).await;
+
+                true
+            }
+            Step::Pass => false,
+        }
+    }
+
+    /// Updates the conversation of a message, the input is sent after the states are unlocked
+    fn step(&self, key: DialogueKey, chat_id: Integer, cmd: Option<String>) -> Step<S> {
+        let now = Instant::now();
+        let mut states = self.dialogue.states.lock().unwrap();
+
+        if states.get(&key).map(|x| self.dialogue.is_expired(x, now)).unwrap_or(false) {
+            return Step::TimedOut(states.remove(&key).unwrap());
+        }
+
+        match (states.get_mut(&key), cmd) {
+            // cancel an active conversation, the command is consumed
+            (Some(_), Some(ref cmd)) if self.dialogue.config.cancel.contains(cmd) => {
+                Step::Cancelled(states.remove(&key).unwrap().state)
+            }
+            // other commands are handled as usual
+            (Some(_), Some(_)) => Step::Pass,
+            (Some(entry), None) => {
+                entry.last_seen = now;
+                entry.chat_id = chat_id;
+
+                Step::Input(entry.state.clone(), false)
+            }
+            (None, Some(ref cmd)) => match self.dialogue.config.entries.iter().find(|(name, _)| name == cmd) {
+                Some((_, state)) => {
+                    states.insert(key, Entry {
+                        state: state.clone(),
+                        chat_id,
+                        last_seen: now,
+                    });
+
+                    Step::Input(state.clone(), true)
+                }
+                None => Step::Pass,
+            },
+            (None, None) => Step::Pass,
+        }
+    }
+
+    /// Takes the callback query if it belongs to a conversation
+    async fn route_callback(&self, request: &RequestHandle, slot: &mut Option<CallbackQuery>) -> bool {
+        let (key, chat_id) = match slot.as_ref() {
+            Some(query) => match query.message {
+                Some(ref msg) => match self.dialogue.config.scope {
+                    DialogueScope::Chat => (DialogueKey(msg.chat.id), msg.chat.id),
+                    DialogueScope::User => (DialogueKey(query.from.id), msg.chat.id),
+                },
+                None => return false,
+            },
+            None => return false,
+        };
+
+        let now = Instant::now();
+
+        let state = match self.dialogue.states.lock().unwrap().get_mut(&key) {
+            Some(entry) if !self.dialogue.is_expired(entry, now) => {
+                entry.last_seen = now;
+                entry.chat_id = chat_id;
+
+                entry.state.clone()
+            }
+            // expired conversations are reported by `expire`
+            _ => return false,
+        };
+
+        let query = slot.take().unwrap();
+        self.send(request, key, chat_id, state, DialogueInput::Callback(query)).await;
+
+        true
+    }
+}
+
+impl<S: Clone + Send + Sync + 'static> DialogueRouter for Router<S> {
+    fn route<'a>(&'a self, request: &'a RequestHandle, update: &'a mut Update, bot_name: Option<&'a str>) -> BoxFuture<'a, bool> {
+        async move {
+            self.route_message(request, &mut update.message, bot_name).await
+                || self.route_callback(request, &mut update.callback_query).await
+        }.boxed()
+    }
+
+    fn accepts(&self, msg: &Message, bot_name: Option<&str>) -> bool {
+        let (key, cmd) = match (self.dialogue.key_of(msg), command::command_of(msg, bot_name)) {
+            (Some(key), Some(cmd)) => (key, cmd),
+            _ => return false,
+        };
+
+        let now = Instant::now();
+        let active = self.dialogue.states.lock().unwrap()
+            .get(&key)
+            .map(|entry| !self.dialogue.is_expired(entry, now))
+            .unwrap_or(false);
+
+        match active {
+            true => self.dialogue.config.cancel.contains(&cmd),
+            false => self.dialogue.config.entries.iter().any(|(name, _)| *name == cmd),
+        }
+    }
+
+    fn deliver<'a>(&'a self, request: &'a RequestHandle, msg: Message, bot_name: Option<&'a str>) -> BoxFuture<'a, ()> {
+        async move {
+            // the conversation may have changed while the guard was checked
+            if !self.route_message(request, &mut Some(msg), bot_name).await {
+                debug!("Dropped a command which no longer starts or cancels a conversation");
+            }
+        }.boxed()
+    }
+
+    fn expire<'a>(&'a self, request: &'a RequestHandle, now: Instant) -> BoxFuture<'a, ()> {
+        let expired: Vec<(DialogueKey, Entry<S>)> = {
+            let mut states = self.dialogue.states.lock().unwrap();
+
+            let keys: Vec<DialogueKey> = states.iter()
+                .filter(|(_, entry)| self.dialogue.is_expired(entry, now))
+                .map(|(key, _)| *key)
+                .collect();
+
+            keys.into_iter().filter_map(|key| states.remove(&key).map(|x| (key, x))).collect()
+        };
+
+        async move {
+            for (key, entry) in expired {
+                debug!("The conversation {:?} timed out", key);
+
+                self.send(request, key, entry.chat_id, entry.state, DialogueInput::TimedOut).await;
+            }
+        }.boxed()
+    }
+
+    fn has_timeout(&self) -> bool {
+        self.dialogue.config.timeout.is_some()
+    }
+}
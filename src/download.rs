@@ -0,0 +1,104 @@
+//! Downloads files from the Telegram server
+//!
+//! The path of a file is resolved with getFile and its content is then fetched from the file
+//! endpoint of the Bot API server. A self-hosted server in local mode returns absolute paths
+//! instead, these files are read directly from the disk. The received content is checked against
+//! the `file_size` reported by getFile.
+//!
+//! ```no_run
+//! use telebot::Bot;
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let bot = Bot::new("TOKEN");
+//!
+//!     match bot.request.download_file_to_path("FILE_ID".into(), "photo.jpg").await {
+//!         Ok(file) => println!("Stored {} bytes", file.file_size.unwrap_or(0)),
+//!         Err(err) => eprintln!("Download failed: {}", err),
+//!     }
+//! }
+//! ```
+
+use crate::error::ErrorKind;
+use crate::objects::Integer;
+
+use std::{path::PathBuf, pin::Pin, sync::{Arc, atomic::{AtomicUsize, Ordering}}};
+
+use failure::{Error, Fail, ResultExt};
+use futures::{future, stream, Stream, StreamExt, TryFutureExt, TryStreamExt};
+use tokio::io::{AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// A stream which yields the content of a file in chunks
+pub type FileStream = Pin<Box<dyn Stream<Item = Result<Vec<u8>, Error>> + Send>>;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Reads a file of a Bot API server in local mode from the disk
+pub fn read_local(path: PathBuf) -> FileStream {
+    tokio::fs::File::open(path)
+        .map_ok(|file| stream::try_unfold(file, |mut file| async move {
+            let mut buf = vec![0; CHUNK_SIZE];
+            let len = file.read(&mut buf).await?;
+
+            if len == 0 {
+                return Ok(None);
+            }
+
+            buf.truncate(len);
+
+            Ok(Some((buf, file)))
+        }))
+        .try_flatten_stream()
+        .map_err(|e| Error::from(e.context(ErrorKind::IO)))
+        .boxed()
+}
+
+/// Fails the stream as soon as it yields more than `size` bytes or ends with fewer bytes. A stream
+/// without a known size is passed through.
+pub fn check_size(content: FileStream, size: Option<Integer>) -> FileStream {
+    let size = match size {
+        Some(size) if size >= 0 => size as usize,
+        _ => return content,
+    };
+
+    let received = Arc::new(AtomicUsize::new(0));
+    let counter = received.clone();
+
+    // checked once the content is complete, the future is only polled after the last chunk
+    let complete = stream::once(async move {
+        let received = received.load(Ordering::SeqCst);
+
+        if received != size {
+            warn!("Expected a file with {} bytes, received {}", size, received);
+
+            return Err(Error::from(ErrorKind::FileSize));
+        }
+
+        Ok(())
+    });
+
+    content
+        .and_then(move |chunk| {
+            if counter.fetch_add(chunk.len(), Ordering::SeqCst) + chunk.len() > size {
+                return future::err(Error::from(ErrorKind::FileSize));
+            }
+
+            future::ok(chunk)
+        })
+        .chain(complete.try_filter_map(|_| future::ok(None)))
+        .boxed()
+}
+
+/// Writes all chunks to `writer` and returns it afterwards
+pub async fn write_all<W>(mut content: FileStream, mut writer: W) -> Result<W, Error>
+where
+    W: AsyncWrite + Unpin + Send,
+{
+    while let Some(chunk) = content.try_next().await? {
+        writer.write_all(&chunk).await.context(ErrorKind::IO)?;
+    }
+
+    writer.flush().await.context(ErrorKind::IO)?;
+
+    Ok(writer)
+}
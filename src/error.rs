@@ -2,6 +2,8 @@ use std::fmt;
 
 use failure::{Backtrace, Context, Fail};
 
+use crate::objects::{Integer, ResponseParameter};
+
 #[derive(Debug)]
 pub struct Error {
     inner: Context<ErrorKind>,
@@ -39,7 +41,7 @@ impl From<ErrorKind> for Error {
 
 impl From<Context<ErrorKind>> for Error {
     fn from(inner: Context<ErrorKind>) -> Error {
-        Error { inner: inner }
+        Error { inner }
     }
 }
 
@@ -89,12 +91,43 @@ pub enum ErrorKind {
     #[fail(display = "Failed to create the interval timer")]
     IntervalTimer,
 
+    #[fail(display = "Failed to bind the webhook listener")]
+    Webhook,
+
     #[fail(display = "Tokio library caused an error")]
     Tokio,
 
     #[fail(display = "Please specify a file")]
     NoFile,
 
+    // indicates that an InputMedia was passed to the send function of another type
+    #[fail(display = "The type of the media doesn't match the function")]
+    MediaType,
+
+    // indicates that the offset store couldn't be read or written
+    #[fail(display = "Failed to read or write the update offset")]
+    OffsetStore,
+
+    // indicates that a session couldn't be read, written or converted
+    #[fail(display = "Failed to read or write a session")]
+    SessionStore,
+
+    // indicates that getFile returned no path, e.g. because the file is too big
+    #[fail(display = "The file has no path and can't be downloaded")]
+    NoFilePath,
+
+    // indicates that the downloaded content doesn't match the size reported by getFile
+    #[fail(display = "The downloaded file has not the expected size")]
+    FileSize,
+
+    // indicates that the pattern of a route is not a valid regex
+    #[fail(display = "Invalid regular expression")]
+    Regex,
+
+    // indicates that a middleware stopped the request
+    #[fail(display = "The request was stopped by a middleware")]
+    Middleware,
+
     #[fail(display = "Expected JSON to be a Map, got something else")]
     JsonNotMap,
 
@@ -103,14 +136,102 @@ pub enum ErrorKind {
     Unknown,
 }
 
+/// An error returned by the Telegram server
+///
+/// Besides the description it carries the numeric error code and the response parameters, which
+/// tell when a request can be repeated or where a migrated group lives now.
 #[derive(Debug, Fail)]
 #[fail(display = "{}", message)]
 pub struct TelegramError {
     message: String,
+    error_code: Option<Integer>,
+    parameters: Option<ResponseParameter>,
 }
 
 impl TelegramError {
     pub fn new(message: String) -> Self {
-        TelegramError { message }
+        TelegramError { message, error_code: None, parameters: None }
+    }
+
+    pub fn with_details(message: String, error_code: Option<Integer>, parameters: Option<ResponseParameter>) -> Self {
+        TelegramError { message, error_code, parameters }
+    }
+
+    /// Searches the chain of causes for an error returned by the Telegram server
+    ///
+    /// ```
+    /// use telebot::{Bot, error::TelegramError};
+    /// use telebot::functions::*;
+    /// use telebot::transport::MockTransport;
+    /// use futures::executor::block_on;
+    /// use serde_json::json;
+    ///
+    /// let mock = MockTransport::new();
+    /// mock.answer_raw("sendMessage", json!({
+    ///     "ok": false,
+    ///     "error_code": 403,
+    ///     "description": "Forbidden: bot was blocked by the user"
+    /// }));
+    ///
+    /// let bot = Bot::new("TOKEN").transport(mock);
+    /// let err = block_on(bot.request.message(42, "Hello".into()).send()).err().unwrap();
+    ///
+    /// assert!(TelegramError::find(&err).unwrap().is_blocked_by_user());
+    /// ```
+    pub fn find(err: &failure::Error) -> Option<&TelegramError> {
+        err.iter_chain().filter_map(|x| x.downcast_ref::<TelegramError>()).next()
+    }
+
+    /// The human-readable description of the error
+    pub fn description(&self) -> &str {
+        &self.message
+    }
+
+    /// The numeric error code, similar to HTTP status codes
+    pub fn error_code(&self) -> Option<Integer> {
+        self.error_code
+    }
+
+    pub fn parameters(&self) -> Option<&ResponseParameter> {
+        self.parameters.as_ref()
+    }
+
+    /// The number of seconds left to wait before the request can be repeated
+    pub fn retry_after(&self) -> Option<Integer> {
+        self.parameters.as_ref().and_then(|x| x.retry_after)
+    }
+
+    /// The new identifier of a group which has been migrated to a supergroup
+    pub fn migrate_to_chat_id(&self) -> Option<Integer> {
+        self.parameters.as_ref().and_then(|x| x.migrate_to_chat_id)
+    }
+
+    /// The request was sent too often and has to be repeated after `retry_after` seconds
+    pub fn is_too_many_requests(&self) -> bool {
+        self.error_code == Some(429) || self.retry_after().is_some()
+    }
+
+    /// The user blocked the bot, messages can't be delivered anymore
+    pub fn is_blocked_by_user(&self) -> bool {
+        self.error_code == Some(403) && self.contains("bot was blocked by the user")
+    }
+
+    /// An edit request didn't change the content of the message
+    pub fn is_message_not_modified(&self) -> bool {
+        self.error_code == Some(400) && self.contains("message is not modified")
+    }
+
+    /// The chat doesn't exist or the bot is not a member of it
+    pub fn is_chat_not_found(&self) -> bool {
+        self.error_code == Some(400) && self.contains("chat not found")
+    }
+
+    /// The group has been upgraded to a supergroup with the id `migrate_to_chat_id`
+    pub fn is_chat_migrated(&self) -> bool {
+        self.migrate_to_chat_id().is_some()
+    }
+
+    fn contains(&self, description: &str) -> bool {
+        self.message.to_lowercase().contains(description)
     }
 }
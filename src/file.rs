@@ -3,8 +3,10 @@
 //! The filename should be such that it represents the content type.
 
 use std::{io::Read, path::PathBuf};
-use failure::Error;
+use failure::{Error, Fail};
+use serde_json::value::Value;
 use crate::error::ErrorKind;
+use crate::objects::Integer;
 
 #[derive(Serialize)]
 #[serde(untagged)]
@@ -13,49 +15,126 @@ pub enum MediaFile {
     MultipleFiles(Vec<FileEntity>)
 }
 
+/// An entry of a media group, serialized as one of the InputMedia types of Telegram
 #[derive(Serialize)]
-#[serde(untagged)]
+#[serde(tag = "type", rename_all = "lowercase")]
 pub enum FileEntity {
     Photo {
-        #[serde(rename = "type")]
-        type_: &'static str,
         media: String,
         #[serde(skip_serializing_if = "Option::is_none")]
         caption: Option<String>,
         #[serde(skip_serializing_if = "Option::is_none")]
         parse_mode: Option<String>,
     },
-    Video {}
+    Video {
+        media: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        thumb: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        caption: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        parse_mode: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        width: Option<Integer>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        height: Option<Integer>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        duration: Option<Integer>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        supports_streaming: Option<bool>,
+    },
+    Audio {
+        media: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        thumb: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        caption: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        parse_mode: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        duration: Option<Integer>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        performer: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        title: Option<String>,
+    },
+    Document {
+        media: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        thumb: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        caption: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        parse_mode: Option<String>,
+    },
 }
 
 pub struct FileList(pub Vec<FileWithCaption>);
 
 impl FileList {
-    pub fn to_metadata(&self) -> Option<MediaFile> {
-        if self.0.len() == 0 {
+    /// Returns the value of the file property of a function, `file_kind` is the name of this
+    /// property. The entries of a media group are always sent as a list.
+    pub fn to_metadata(&self, file_kind: &str) -> Option<MediaFile> {
+        if self.0.is_empty() {
             None
-        } else if self.0.len() == 1 {
-            Some(MediaFile::SingleFile(self.0.iter().map(|x| x.file.name()).next().unwrap()))
+        } else if self.0.len() == 1 && file_kind != "media" {
+            Some(MediaFile::SingleFile(self.0[0].file.name()))
         } else {
-            let entities = self.0.iter().map(|x| {
-                FileEntity::Photo {
-                    type_: "photo",
-                    media: x.file.name(),
-                    caption: x.caption.clone(),
-                    parse_mode: x.parse_mode.clone()
-                }
-            }).collect();
+            let entities = self.0.iter().map(FileWithCaption::to_entity).collect();
 
             Some(MediaFile::MultipleFiles(entities))
         }
     }
 
+    /// Copies the caption, parse mode, thumbnail and the type specific properties of a single
+    /// file to the message. Properties which are already set on the function are kept.
+    pub fn apply_properties(&self, file_kind: &str, msg: &mut Value) -> Result<(), Error> {
+        let entry = match self.0.as_slice() {
+            [entry] if file_kind != "media" => entry,
+            _ => return Ok(()),
+        };
+
+        if let Some(ref kind) = entry.kind {
+            if kind.name() != file_kind {
+                return Err(Error::from(ErrorKind::MediaType));
+            }
+        }
+
+        let properties = serde_json::to_value(entry.to_entity())
+            .map_err(|e| Error::from(e.context(ErrorKind::JsonSerialize)))?;
+
+        let (msg, properties) = match (msg, properties) {
+            (Value::Object(msg), Value::Object(properties)) => (msg, properties),
+            _ => return Err(Error::from(ErrorKind::JsonNotMap)),
+        };
+
+        for (key, value) in properties {
+            if key != "type" && key != "media" {
+                msg.entry(key).or_insert(value);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns all files together with the thumbnails, photos have no thumbnail
     pub fn into_files(self) -> Option<Vec<File>> {
-        if self.0.len() == 0 {
-            None
-        } else {
-            Some(self.0.into_iter().map(|x| x.file).collect())
+        if self.0.is_empty() {
+            return None;
+        }
+
+        let mut files = Vec::new();
+
+        for entry in self.0 {
+            files.push(entry.file);
+
+            match (entry.kind, entry.thumb) {
+                (None, _) | (Some(MediaKind::Photo), _) | (_, None) => {}
+                (_, Some(thumb)) => files.push(thumb),
+            }
         }
+
+        Some(files)
     }
 
     pub fn push(&mut self, val: FileWithCaption) {
@@ -94,8 +173,42 @@ impl File {
     }
 }
 
+/// The type of a media group entry together with the properties which only apply to this type
+#[derive(Clone, Debug)]
+pub enum MediaKind {
+    Photo,
+    Video {
+        width: Option<Integer>,
+        height: Option<Integer>,
+        duration: Option<Integer>,
+        supports_streaming: Option<bool>,
+    },
+    Audio {
+        duration: Option<Integer>,
+        performer: Option<String>,
+        title: Option<String>,
+    },
+    Document,
+}
+
+impl MediaKind {
+    /// The name of the type, which is also the name of the file property of the send function
+    fn name(&self) -> &'static str {
+        match self {
+            MediaKind::Photo => "photo",
+            MediaKind::Video { .. } => "video",
+            MediaKind::Audio { .. } => "audio",
+            MediaKind::Document => "document",
+        }
+    }
+}
+
+/// An entry of a file list, `kind` is empty for plain files. They are sent as photos in a media
+/// group.
 pub struct FileWithCaption {
     file: File,
+    kind: Option<MediaKind>,
+    thumb: Option<File>,
     caption: Option<String>,
     parse_mode: Option<String>
 }
@@ -103,7 +216,9 @@ pub struct FileWithCaption {
 impl FileWithCaption {
     pub fn new_empty(file: File) -> FileWithCaption {
         FileWithCaption {
-            file: file,
+            file,
+            kind: None,
+            thumb: None,
             caption: None,
             parse_mode: None
         }
@@ -111,28 +226,213 @@ impl FileWithCaption {
 
     pub fn new(file: File, caption: String, parse_mode: String) -> FileWithCaption {
         FileWithCaption {
-            file: file,
             caption: Some(caption),
-            parse_mode: Some(parse_mode)
+            parse_mode: Some(parse_mode),
+            ..FileWithCaption::new_empty(file)
+        }
+    }
+
+    fn to_entity(&self) -> FileEntity {
+        let media = self.file.name();
+        let thumb = self.thumb.as_ref().map(File::name);
+        let caption = self.caption.clone();
+        let parse_mode = self.parse_mode.clone();
+
+        match self.kind.clone().unwrap_or(MediaKind::Photo) {
+            MediaKind::Photo => FileEntity::Photo { media, caption, parse_mode },
+            MediaKind::Video { width, height, duration, supports_streaming } => FileEntity::Video {
+                media, thumb, caption, parse_mode, width, height, duration, supports_streaming
+            },
+            MediaKind::Audio { duration, performer, title } => FileEntity::Audio {
+                media, thumb, caption, parse_mode, duration, performer, title
+            },
+            MediaKind::Document => FileEntity::Document { media, thumb, caption, parse_mode },
         }
     }
 }
 
+/// A single entry of a media group with its type, caption and parse mode
+///
+/// ```no_run
+/// use telebot::Bot;
+/// use telebot::file::{File, InputMedia};
+/// use telebot::functions::*;
+///
+/// # async fn send_album() -> Result<(), failure::Error> {
+/// let bot = Bot::new("TOKEN");
+///
+/// bot.request.mediagroup(12345)
+///     .file(InputMedia::photo("examples/bee.jpg").caption("A bee"))
+///     .file(InputMedia::video(File::Url("https://example.com/bee.mp4".into()))
+///         .caption("<b>Flying</b>")
+///         .parse_mode("HTML")
+///         .supports_streaming(true))
+///     .send()
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// A media with a single file can also be passed to the function of its type, e.g. a video to
+/// `video`. Its caption and properties are then sent as properties of the function.
+pub struct InputMedia {
+    file: Result<File, Error>,
+    kind: MediaKind,
+    thumb: Option<Result<File, Error>>,
+    caption: Option<String>,
+    parse_mode: Option<String>,
+}
+
+impl InputMedia {
+    fn new<S: TryIntoFile>(file: S, kind: MediaKind) -> InputMedia where S::Error: Into<Error> {
+        InputMedia {
+            file: file.try_into().map_err(Into::into),
+            kind,
+            thumb: None,
+            caption: None,
+            parse_mode: None,
+        }
+    }
+
+    pub fn photo<S: TryIntoFile>(file: S) -> InputMedia where S::Error: Into<Error> {
+        InputMedia::new(file, MediaKind::Photo)
+    }
+
+    pub fn video<S: TryIntoFile>(file: S) -> InputMedia where S::Error: Into<Error> {
+        InputMedia::new(file, MediaKind::Video { width: None, height: None, duration: None, supports_streaming: None })
+    }
+
+    pub fn audio<S: TryIntoFile>(file: S) -> InputMedia where S::Error: Into<Error> {
+        InputMedia::new(file, MediaKind::Audio { duration: None, performer: None, title: None })
+    }
+
+    pub fn document<S: TryIntoFile>(file: S) -> InputMedia where S::Error: Into<Error> {
+        InputMedia::new(file, MediaKind::Document)
+    }
+
+    pub fn caption<S: Into<String>>(mut self, caption: S) -> InputMedia {
+        self.caption = Some(caption.into());
+
+        self
+    }
+
+    pub fn parse_mode<S: Into<String>>(mut self, parse_mode: S) -> InputMedia {
+        self.parse_mode = Some(parse_mode.into());
+
+        self
+    }
+
+    /// Sets the thumbnail of a video, audio or document. It has to be uploaded as a new file.
+    pub fn thumb<S: TryIntoFile>(mut self, thumb: S) -> InputMedia where S::Error: Into<Error> {
+        self.thumb = Some(thumb.try_into().map_err(Into::into));
+
+        self
+    }
+
+    /// Sets the width of a video
+    pub fn width(mut self, val: Integer) -> InputMedia {
+        if let MediaKind::Video { ref mut width, .. } = self.kind {
+            *width = Some(val);
+        }
+
+        self
+    }
+
+    /// Sets the height of a video
+    pub fn height(mut self, val: Integer) -> InputMedia {
+        if let MediaKind::Video { ref mut height, .. } = self.kind {
+            *height = Some(val);
+        }
+
+        self
+    }
+
+    /// Sets the duration of a video or audio in seconds
+    pub fn duration(mut self, val: Integer) -> InputMedia {
+        match self.kind {
+            MediaKind::Video { ref mut duration, .. } | MediaKind::Audio { ref mut duration, .. } => {
+                *duration = Some(val);
+            }
+            _ => {}
+        }
+
+        self
+    }
+
+    /// Tells whether a video can be streamed
+    pub fn supports_streaming(mut self, val: bool) -> InputMedia {
+        if let MediaKind::Video { ref mut supports_streaming, .. } = self.kind {
+            *supports_streaming = Some(val);
+        }
+
+        self
+    }
+
+    /// Sets the performer of an audio
+    pub fn performer<S: Into<String>>(mut self, val: S) -> InputMedia {
+        if let MediaKind::Audio { ref mut performer, .. } = self.kind {
+            *performer = Some(val.into());
+        }
+
+        self
+    }
+
+    /// Sets the title of an audio
+    pub fn title<S: Into<String>>(mut self, val: S) -> InputMedia {
+        if let MediaKind::Audio { ref mut title, .. } = self.kind {
+            *title = Some(val.into());
+        }
+
+        self
+    }
+}
+
+/// Converts a value into an entry of a file list. Plain files are sent without caption and are
+/// treated as photos in a media group.
+pub trait TryIntoMedia: Sized {
+    type Error;
+    fn try_into_media(self) -> Result<FileWithCaption, Self::Error>;
+}
+
+impl<T: TryIntoFile> TryIntoMedia for T {
+    type Error = T::Error;
+
+    fn try_into_media(self) -> Result<FileWithCaption, Self::Error> {
+        self.try_into().map(FileWithCaption::new_empty)
+    }
+}
+
+impl TryIntoMedia for InputMedia {
+    type Error = Error;
+
+    fn try_into_media(self) -> Result<FileWithCaption, Self::Error> {
+        let InputMedia { file, kind, thumb, caption, parse_mode } = self;
+
+        Ok(FileWithCaption {
+            file: file?,
+            kind: Some(kind),
+            thumb: thumb.transpose()?,
+            caption,
+            parse_mode,
+        })
+    }
+}
+
 pub trait TryIntoFile: Sized {
     type Error;
     fn try_into(self) -> Result<File, Self::Error>;
 }
 
 impl TryIntoFile for File {
-    type Error = ();
-    
+    type Error = Error;
+
     fn try_into(self) -> Result<File, Self::Error> {
         Ok(self)
     }
 }
 
 /// Construct a Telegram file from a local path
-impl<'a> TryIntoFile for &'a str {
+impl TryIntoFile for &str {
     type Error = Error;
 
     fn try_into(self) -> Result<File, Self::Error> {
@@ -149,7 +449,7 @@ impl<'a> TryIntoFile for &'a str {
 }
 
 /// Construct a Telegram file from an object which implements the Read trait
-impl<'a, S: Read + Send + 'static> TryIntoFile for (&'a str, S) {
+impl<S: Read + Send + 'static> TryIntoFile for (&str, S) {
     type Error = Error;
 
     fn try_into(self) -> Result<File, Self::Error>
@@ -0,0 +1,139 @@
+//! Filters for messages which are not commands
+//!
+//! A `MessageFilter` selects messages by their content and by the type of the chat they were
+//! sent in. Streams registered with `Bot::messages` receive every non-command message which
+//! matches their filter:
+//!
+//! ```
+//! use telebot::Bot;
+//! use telebot::filter::{ChatKind, ContentKind, MessageFilter};
+//!
+//! let mut bot = Bot::new("TOKEN");
+//!
+//! let photos = bot.messages(MessageFilter::new()
+//!     .content(ContentKind::Photo)
+//!     .content(ContentKind::Document)
+//!     .chat(ChatKind::Private));
+//! ```
+
+use crate::objects::Message;
+
+/// The content of a message
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ContentKind {
+    Text,
+    Audio,
+    Document,
+    Game,
+    Photo,
+    Sticker,
+    Video,
+    Voice,
+    Contact,
+    Location,
+    Venue,
+    NewChatMembers,
+    LeftChatMember,
+    NewChatTitle,
+    NewChatPhoto,
+    DeleteChatPhoto,
+    ChatCreated,
+    Migration,
+    PinnedMessage,
+}
+
+impl ContentKind {
+    /// Checks whether the message has this content
+    pub fn matches(self, msg: &Message) -> bool {
+        match self {
+            ContentKind::Text => msg.text.is_some(),
+            ContentKind::Audio => msg.audio.is_some(),
+            ContentKind::Document => msg.document.is_some(),
+            ContentKind::Game => msg.game.is_some(),
+            ContentKind::Photo => msg.photo.is_some(),
+            ContentKind::Sticker => msg.sticker.is_some(),
+            ContentKind::Video => msg.video.is_some(),
+            ContentKind::Voice => msg.voice.is_some(),
+            ContentKind::Contact => msg.contact.is_some(),
+            ContentKind::Location => msg.location.is_some(),
+            ContentKind::Venue => msg.venue.is_some(),
+            ContentKind::NewChatMembers => msg.new_chat_members.is_some() || msg.new_chat_member.is_some(),
+            ContentKind::LeftChatMember => msg.left_chat_member.is_some(),
+            ContentKind::NewChatTitle => msg.new_chat_title.is_some(),
+            ContentKind::NewChatPhoto => msg.new_chat_photo.is_some(),
+            ContentKind::DeleteChatPhoto => msg.delete_chat_photo.is_some(),
+            ContentKind::ChatCreated => {
+                msg.group_chat_created.is_some()
+                    || msg.supergroup_chat_created.is_some()
+                    || msg.channel_chat_created.is_some()
+            }
+            ContentKind::Migration => msg.migrate_to_chat_id.is_some() || msg.migrate_from_chat_id.is_some(),
+            ContentKind::PinnedMessage => msg.pinned_message.is_some(),
+        }
+    }
+}
+
+/// The type of a chat
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ChatKind {
+    Private,
+    Group,
+    Supergroup,
+    Channel,
+}
+
+impl ChatKind {
+    /// The name of the chat type used by Telegram
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ChatKind::Private => "private",
+            ChatKind::Group => "group",
+            ChatKind::Supergroup => "supergroup",
+            ChatKind::Channel => "channel",
+        }
+    }
+
+    /// Checks whether the message was sent in a chat of this type
+    pub fn matches(self, msg: &Message) -> bool {
+        msg.chat.kind == self.as_str()
+    }
+}
+
+/// Selects messages by content and chat type
+///
+/// A message matches if it has any of the content kinds and was sent in any of the chat types.
+/// An empty list of content kinds or chat types matches every message.
+#[derive(Clone, Debug, Default)]
+pub struct MessageFilter {
+    content: Vec<ContentKind>,
+    chats: Vec<ChatKind>,
+}
+
+impl MessageFilter {
+    /// Creates a filter which matches every message
+    pub fn new() -> MessageFilter {
+        MessageFilter::default()
+    }
+
+    /// Also accept messages with this content
+    pub fn content(mut self, kind: ContentKind) -> MessageFilter {
+        self.content.push(kind);
+
+        self
+    }
+
+    /// Also accept messages from chats of this type
+    pub fn chat(mut self, kind: ChatKind) -> MessageFilter {
+        self.chats.push(kind);
+
+        self
+    }
+
+    /// Checks whether the message passes the filter
+    pub fn matches(&self, msg: &Message) -> bool {
+        let content = self.content.is_empty() || self.content.iter().any(|x| x.matches(msg));
+        let chat = self.chats.is_empty() || self.chats.iter().any(|x| x.matches(msg));
+
+        content && chat
+    }
+}
@@ -6,7 +6,6 @@ use std::convert::From;
 
 use serde_json;
 use failure::{Error, Fail};
-use futures::Future;
 use erased_serde::Serialize;
 
 use crate::bot::RequestHandle;
@@ -22,9 +21,9 @@ pub enum ParseMode {
     Text,
 }
 
-impl Into<String> for ParseMode {
-    fn into(self) -> String {
-        let tmp = match self {
+impl From<ParseMode> for String {
+    fn from(mode: ParseMode) -> String {
+        let tmp = match mode {
             ParseMode::Markdown => "Markdown",
             ParseMode::HTML => "HTML",
             ParseMode::Text => "Text",
@@ -62,10 +61,10 @@ impl ::serde::Serialize for ReplyMarkup {
         use self::ReplyMarkup::*;
 
         match self {
-            &InlineKeyboardMarkup(ref x) => x.serialize(serializer),
-            &ReplyKeyboardMarkup(ref x) => x.serialize(serializer),
-            &ReplyKeyboardRemove(ref x) => x.serialize(serializer),
-            &ForceReply(ref x) => x.serialize(serializer),
+            InlineKeyboardMarkup(x) => x.serialize(serializer),
+            ReplyKeyboardMarkup(x) => x.serialize(serializer),
+            ReplyKeyboardRemove(x) => x.serialize(serializer),
+            ForceReply(x) => x.serialize(serializer),
         }
     }
 }
@@ -94,9 +93,9 @@ impl From<objects::ForceReply> for ReplyMarkup {
     }
 }
 
-impl Into<String> for Action {
-    fn into(self) -> String {
-        let tmp = match self {
+impl From<Action> for String {
+    fn from(action: Action) -> String {
+        let tmp = match action {
             Action::Typing => "Typing",
             Action::UploadPhoto => "UploadPhoto",
             Action::RecordVideo => "RecordVideo",
@@ -136,6 +135,51 @@ pub struct GetUpdates {
     allowed_updates: Option<Vec<String>>,
 }
 
+/// Use this method to specify a url and receive incoming updates via an outgoing webhook. Whenever
+/// there is an update for the bot, we will send an HTTPS POST request to the specified url,
+/// containing a JSON-serialized Update. In case of an unsuccessful request, we will give up after
+/// a reasonable amount of attempts. Returns True on success.
+///
+/// A self-signed public key certificate can be uploaded with the file function.
+#[derive(TelegramFunction, Serialize)]
+#[call = "setWebhook"]
+#[answer = "Boolean"]
+#[function = "set_webhook"]
+#[file_kind = "certificate"]
+pub struct SetWebhook {
+    url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    certificate: Option<MediaFile>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_connections: Option<Integer>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    allowed_updates: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    drop_pending_updates: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    secret_token: Option<String>,
+}
+
+/// Use this method to remove webhook integration if you decide to switch back to getUpdates.
+/// Returns True on success.
+#[derive(TelegramFunction, Serialize)]
+#[call = "deleteWebhook"]
+#[answer = "Boolean"]
+#[function = "delete_webhook"]
+pub struct DeleteWebhook {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    drop_pending_updates: Option<bool>,
+}
+
+/// Use this method to get current webhook status. Requires no parameters. On success, returns a
+/// WebhookInfo object. If the bot is using getUpdates, will return an object with the url field
+/// empty.
+#[derive(TelegramFunction, Serialize)]
+#[call = "getWebhookInfo"]
+#[answer = "WebhookInfo"]
+#[function = "get_webhook_info"]
+pub struct GetWebhookInfo;
+
 /// Use this method to send text messages. On success, the sent Message is returned.
 #[derive(TelegramFunction, Serialize)]
 #[call = "sendMessage"]
@@ -670,11 +714,23 @@ pub struct GetChat {
 #[derive(TelegramFunction, Serialize)]
 #[call = "getChatAdministrators"]
 #[answer = "Vector<objects::ChatMember>"]
-#[function = "unban_chat_administrators"]
+#[function = "get_chat_administrators"]
 pub struct GetChatAdministrators {
     chat_id: Integer,
 }
 
+/// The former name of `get_chat_administrators`
+pub trait FunctionUnbanChatAdministrators {
+    #[deprecated(note = "renamed to get_chat_administrators")]
+    fn unban_chat_administrators(&self, chat_id: Integer) -> WrapperGetChatAdministrators;
+}
+
+impl FunctionUnbanChatAdministrators for RequestHandle {
+    fn unban_chat_administrators(&self, chat_id: Integer) -> WrapperGetChatAdministrators {
+        self.get_chat_administrators(chat_id)
+    }
+}
+
 /// Use this method to get the number of members in a chat. Returns Int on success.
 #[derive(TelegramFunction, Serialize)]
 #[call = "getChatMembersCount"]
@@ -804,7 +860,8 @@ pub struct EditMessageReplyMarkup {
 /// - Bots granted can_post_messages permissions can delete outgoing messages in channels.
 /// - If the bot is an administrator of a group, it can delete any message there.
 /// - If the bot has can_delete_messages permission in a supergroup or a channel, it can delete any
-///		message there.
+///   message there.
+///
 /// Returns True on success.
 #[derive(TelegramFunction, Serialize)]
 #[call = "deleteMessage"]
@@ -853,3 +910,42 @@ pub struct AddStickerToSet {
 pub struct DeleteStickerFromSet {
     sticker: String,
 }
+
+/// Use this method to change the list of the bot's commands. Returns True on success.
+#[derive(TelegramFunction, Serialize)]
+#[call = "setMyCommands"]
+#[answer = "Boolean"]
+#[function = "set_my_commands"]
+pub struct SetMyCommands {
+    commands: Vec<objects::BotCommand>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scope: Option<objects::BotCommandScope>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    language_code: Option<String>,
+}
+
+/// Use this method to get the current list of the bot's commands for the given scope and user
+/// language. Returns an Array of BotCommand objects.
+#[derive(TelegramFunction, Serialize)]
+#[call = "getMyCommands"]
+#[answer = "Vector<objects::BotCommand>"]
+#[function = "get_my_commands"]
+pub struct GetMyCommands {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scope: Option<objects::BotCommandScope>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    language_code: Option<String>,
+}
+
+/// Use this method to delete the list of the bot's commands for the given scope and user
+/// language. Higher level commands will be shown to affected users. Returns True on success.
+#[derive(TelegramFunction, Serialize)]
+#[call = "deleteMyCommands"]
+#[answer = "Boolean"]
+#[function = "delete_my_commands"]
+pub struct DeleteMyCommands {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scope: Option<objects::BotCommandScope>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    language_code: Option<String>,
+}
@@ -0,0 +1,275 @@
+//! Restricts commands to some users, chats or chat members
+//!
+//! A `Guard` is attached to a command name with `Bot::guard` and checked before a command
+//! reaches its handler, no matter if it was registered with `new_cmd`, `command` or `commands`.
+//! Users who are not allowed get a reply, which can be changed or turned off:
+//!
+//! ```
+//! use telebot::Bot;
+//! use telebot::guard::Guard;
+//!
+//! let mut bot = Bot::new("TOKEN");
+//!
+//! let ban = bot.new_cmd("/ban");
+//! bot.guard("/ban", Guard::admins().deny_reply("Only admins can ban users"));
+//!
+//! let deploy = bot.new_cmd("/deploy");
+//! bot.guard("/deploy", Guard::users(&[1234, 5678]).silent());
+//!
+//! let stats = bot.new_cmd("/stats");
+//! bot.guard("/stats", Guard::chats(&[-1001234]).and(Guard::admins()).or(Guard::users(&[1234])));
+//! ```
+//!
+//! The status of chat members is requested with getChatAdministrators resp. getChatMember and
+//! cached, see `Bot::member_cache_ttl`.
+
+use crate::bot::RequestHandle;
+use crate::objects::{Integer, Message};
+
+use std::{collections::{HashMap, HashSet}, hash::Hash, sync::{Arc, Mutex}, time::{Duration, Instant}};
+
+use failure::Error;
+use futures::{future::{BoxFuture, Shared}, Future, FutureExt, TryFutureExt};
+
+/// The statuses of chat administrators
+const ADMIN_STATUS: &[&str] = &["creator", "administrator"];
+
+/// Passes an allowed command on to its handler, the future waits for room in the queue
+pub(crate) type Deliver = Box<dyn FnOnce(RequestHandle, Message) -> BoxFuture<'static, ()> + Send>;
+
+/// A command which waits for its permission check
+pub(crate) type GuardJob = (RequestHandle, Message, Arc<Guard>, Deliver);
+
+#[derive(Clone, Debug)]
+enum Rule {
+    Users(HashSet<Integer>),
+    Chats(HashSet<Integer>),
+    Status(Vec<String>),
+    Any(Vec<Rule>),
+    All(Vec<Rule>),
+}
+
+impl Rule {
+    fn needs_status(&self) -> bool {
+        match *self {
+            Rule::Status(_) => true,
+            Rule::Any(ref rules) | Rule::All(ref rules) => rules.iter().any(Rule::needs_status),
+            _ => false,
+        }
+    }
+
+    /// Whether only administrator statuses are checked, which are all known from the list of
+    /// administrators
+    fn admins_only(&self) -> bool {
+        match *self {
+            Rule::Status(ref status) => status.iter().all(|x| ADMIN_STATUS.contains(&x.as_str())),
+            Rule::Any(ref rules) | Rule::All(ref rules) => rules.iter().all(Rule::admins_only),
+            _ => true,
+        }
+    }
+
+    /// Evaluates the rule, `status` is the status of the sender in the chat
+    fn allows(&self, msg: &Message, status: Option<&str>) -> bool {
+        match *self {
+            Rule::Users(ref users) => msg.from.as_ref().map(|x| users.contains(&x.id)).unwrap_or(false),
+            Rule::Chats(ref chats) => chats.contains(&msg.chat.id),
+            Rule::Status(ref allowed) => status.map(|x| allowed.iter().any(|y| y == x)).unwrap_or(false),
+            Rule::Any(ref rules) => rules.iter().any(|x| x.allows(msg, status)),
+            Rule::All(ref rules) => rules.iter().all(|x| x.allows(msg, status)),
+        }
+    }
+}
+
+/// Decides who may use a command
+#[derive(Clone, Debug)]
+pub struct Guard {
+    rule: Rule,
+    denial: Option<String>,
+}
+
+impl Guard {
+    fn new(rule: Rule) -> Guard {
+        Guard {
+            rule,
+            denial: Some("You are not allowed to use this command.".into()),
+        }
+    }
+
+    /// Allows the users with these ids
+    pub fn users(ids: &[Integer]) -> Guard {
+        Guard::new(Rule::Users(ids.iter().cloned().collect()))
+    }
+
+    /// Allows everybody in the chats with these ids
+    pub fn chats(ids: &[Integer]) -> Guard {
+        Guard::new(Rule::Chats(ids.iter().cloned().collect()))
+    }
+
+    /// Allows chat members with one of these statuses, e.g. `creator`, `administrator`,
+    /// `member` or `restricted`
+    ///
+    /// Private chats have no members, so the status is unknown there and the command denied.
+    pub fn status(status: &[&str]) -> Guard {
+        Guard::new(Rule::Status(status.iter().map(|x| x.to_string()).collect()))
+    }
+
+    /// Allows the creator and the administrators of a group
+    pub fn admins() -> Guard {
+        Guard::status(ADMIN_STATUS)
+    }
+
+    /// Allows users who pass this or the other guard, the denial reply of this guard is kept
+    pub fn or(self, other: Guard) -> Guard {
+        Guard {
+            rule: Rule::Any(vec![self.rule, other.rule]),
+            denial: self.denial,
+        }
+    }
+
+    /// Allows users who pass both guards, the denial reply of this guard is kept
+    pub fn and(self, other: Guard) -> Guard {
+        Guard {
+            rule: Rule::All(vec![self.rule, other.rule]),
+            denial: self.denial,
+        }
+    }
+
+    /// Replies with this text to users who are not allowed
+    pub fn deny_reply(mut self, text: &str) -> Guard {
+        self.denial = Some(text.into());
+
+        self
+    }
+
+    /// Ignores users who are not allowed
+    pub fn silent(mut self) -> Guard {
+        self.denial = None;
+
+        self
+    }
+
+    /// The reply to users who are not allowed
+    pub fn denial(&self) -> Option<&str> {
+        self.denial.as_deref()
+    }
+
+    /// Checks the message without requests, returns `None` if the status of the sender is needed
+    pub fn check_now(&self, msg: &Message) -> Option<bool> {
+        match self.rule.needs_status() {
+            true if msg.chat.kind != "private" => None,
+            _ => Some(self.rule.allows(msg, None)),
+        }
+    }
+
+    /// Checks the message and requests the status of the sender if necessary
+    pub async fn check(&self, msg: &Message, request: &RequestHandle, cache: &MemberCache) -> Result<bool, Error> {
+        if let Some(allowed) = self.check_now(msg) {
+            return Ok(allowed);
+        }
+
+        let user_id = match msg.from {
+            Some(ref user) => user.id,
+            None => return Ok(false),
+        };
+
+        let status = cache.status(request, msg.chat.id, user_id, self.rule.admins_only()).await?;
+
+        Ok(self.rule.allows(msg, status.as_deref()))
+    }
+}
+
+/// The administrators of a chat with their status, by user id
+type Admins = HashMap<Integer, String>;
+
+/// A request which is in flight, shared by all checks which wait for its answer
+type Lookup<T> = Shared<BoxFuture<'static, Result<T, Arc<Error>>>>;
+
+/// Caches the administrators of chats and the status of chat members
+///
+/// Entries are requested again after the TTL, so that promotions become effective. Checks which
+/// need the same entry at the same time wait for a single request.
+pub struct MemberCache {
+    ttl: Duration,
+    admins: Mutex<HashMap<Integer, (Instant, Admins)>>,
+    members: Mutex<HashMap<(Integer, Integer), (Instant, String)>>,
+    pending_admins: Mutex<HashMap<Integer, Lookup<Admins>>>,
+    pending_members: Mutex<HashMap<(Integer, Integer), Lookup<String>>>,
+}
+
+impl MemberCache {
+    pub fn new(ttl: Duration) -> MemberCache {
+        MemberCache {
+            ttl,
+            admins: Mutex::new(HashMap::new()),
+            members: Mutex::new(HashMap::new()),
+            pending_admins: Mutex::new(HashMap::new()),
+            pending_members: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the status of a user in a chat
+    ///
+    /// If only administrator statuses matter, the whole list of administrators is requested once
+    /// per chat. Users who aren't on the list have no status then.
+    pub async fn status(&self, request: &RequestHandle, chat_id: Integer, user_id: Integer, admins_only: bool) -> Result<Option<String>, Error> {
+        use crate::functions::{FunctionGetChatAdministrators, FunctionGetChatMember};
+
+        let now = Instant::now();
+
+        if admins_only {
+            if let Some((at, ref admins)) = self.admins.lock().unwrap().get(&chat_id) {
+                if now.duration_since(*at) < self.ttl {
+                    return Ok(admins.get(&user_id).cloned());
+                }
+            }
+
+            let admins = request.get_chat_administrators(chat_id).send()
+                .map_ok(|(_, admins)| admins.into_iter().map(|x| (x.user.id, x.status)).collect());
+            let admins = lookup(&self.pending_admins, chat_id, admins).await?;
+            let status = admins.get(&user_id).cloned();
+
+            self.admins.lock().unwrap().insert(chat_id, (now, admins));
+
+            return Ok(status);
+        }
+
+        if let Some((at, ref status)) = self.members.lock().unwrap().get(&(chat_id, user_id)) {
+            if now.duration_since(*at) < self.ttl {
+                return Ok(Some(status.clone()));
+            }
+        }
+
+        let member = request.get_chat_member(chat_id, user_id).send().map_ok(|(_, member)| member.status);
+        let status = lookup(&self.pending_members, (chat_id, user_id), member).await?;
+
+        self.members.lock().unwrap().insert((chat_id, user_id), (now, status.clone()));
+
+        Ok(Some(status))
+    }
+
+    /// Forgets all cached statuses of a chat
+    pub fn invalidate(&self, chat_id: Integer) {
+        self.admins.lock().unwrap().remove(&chat_id);
+        self.members.lock().unwrap().retain(|key, _| key.0 != chat_id);
+    }
+}
+
+/// Waits for the pending request of `key` or sends `fetch` if there is none
+async fn lookup<K, T, F>(pending: &Mutex<HashMap<K, Lookup<T>>>, key: K, fetch: F) -> Result<T, Error>
+where
+    K: Copy + Eq + Hash,
+    T: Clone + Send + Sync + 'static,
+    F: Future<Output = Result<T, Error>> + Send + 'static,
+{
+    let shared = pending.lock().unwrap()
+        .entry(key)
+        .or_insert_with(|| fetch.map_err(Arc::new).boxed().shared())
+        .clone();
+
+    let res = shared.await;
+
+    // the request is forgotten once it is answered, the callers cache the answer
+    pending.lock().unwrap().remove(&key);
+
+    res.map_err(|err| failure::err_msg(err.to_string()))
+}
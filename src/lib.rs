@@ -1,23 +1,24 @@
 //! # Write a telegram bot in Rust
 //!
-//! This library allows you to write a Telegram Bot in Rust. It's an almost complete wrapper for the Telegram Bot API and uses hyper to send a request to the Telegram server. Each Telegram function call returns a future and carries the actual bot and the answer.
+//! This library allows you to write a Telegram Bot in Rust. It's an almost complete wrapper for the Telegram Bot API and uses hyper to send a request to the Telegram server. Each Telegram function call is an async function which returns the actual bot and the answer.
 //! You can find all available functions in src/functions.rs. The crate telebot-derive implements all
 //! required getter, setter and send functions automatically.
 //!
 //! # Example usage
 //!
-//! ```
+//! ```no_run
 //! use telebot::Bot;
-//! use futures::stream::Stream;
+//! use futures::{future, TryStreamExt};
 //! use std::env;
-//! 
+//!
 //! // import all available functions
 //! use telebot::functions::*;
-//! 
-//! fn main() {
+//!
+//! #[tokio::main]
+//! async fn main() {
 //!     // Create the bot
 //!     let mut bot = Bot::new(&env::var("TELEGRAM_BOT_KEY").unwrap()).update_interval(200);
-//! 
+//!
 //!     // Register a reply command which answers a message
 //!     let handle = bot.new_cmd("/reply")
 //!         .and_then(|(bot, msg)| {
@@ -25,17 +26,18 @@
 //!             if text.is_empty() {
 //!                 text = "<empty>".into();
 //!             }
-//! 
+//!
 //!             bot.message(msg.chat.id, text).send()
 //!         })
-//!         .for_each(|_| Ok(()));
-//! 
-//!     bot.run_with(handle);
+//!         .try_for_each(|_| future::ok(()));
+//!
+//!     bot.run_with(handle).await;
 //! }
 //! ```
 
 #![allow(bare_trait_objects)]
 #![allow(unused_attributes)]
+#![allow(non_local_definitions)]
 
 #[macro_use]
 extern crate telebot_derive;
@@ -43,7 +45,6 @@ extern crate telebot_derive;
 #[macro_use]
 extern crate log;
 
-extern crate hyper_multipart_rfc7578 as hyper_multipart;
 #[macro_use]
 extern crate serde;
 
@@ -58,3 +59,20 @@ pub mod error;
 pub mod objects;
 pub mod functions;
 pub mod file;
+pub mod webhook;
+pub mod transport;
+pub mod retry;
+pub mod ratelimit;
+pub mod migration;
+pub mod download;
+pub mod blocking;
+pub mod shutdown;
+pub mod offset;
+pub mod filter;
+pub mod router;
+pub mod command;
+pub mod dialogue;
+pub mod session;
+pub mod middleware;
+pub mod guard;
+pub mod queue;
@@ -0,0 +1,129 @@
+//! Hooks which run around every update and every request
+//!
+//! A `Middleware` sees each update before it is dispatched. It can inspect or change the update,
+//! attach annotations which the handlers read from their `RequestHandle`, or stop the update so
+//! that no handler receives it. The same middleware also sees every request which is sent to the
+//! Telegram server and its answer.
+//!
+//! Updates pass the middlewares in the order they were added, answers in the reverse order.
+//!
+//! ```
+//! use telebot::Bot;
+//! use telebot::middleware::{Flow, Middleware, RequestContext, UpdateContext};
+//! use failure::Error;
+//!
+//! struct Banned(Vec<i64>);
+//!
+//! struct Log;
+//!
+//! impl Middleware for Log {
+//!     fn on_request(&self, ctx: &mut RequestContext) -> Flow {
+//!         println!("Calling {}", ctx.function);
+//!         Flow::Continue
+//!     }
+//!
+//!     fn on_response(&self, function: &'static str, result: &Result<String, Error>) {
+//!         println!("{} answered {:?}", function, result.is_ok());
+//!     }
+//! }
+//!
+//! let banned = Banned(vec![1234]);
+//!
+//! let bot = Bot::new("TOKEN")
+//!     .middleware(Log)
+//!     .middleware(move |ctx: &mut UpdateContext| {
+//!         let user = ctx.update.message.as_ref().and_then(|x| x.from.as_ref()).map(|x| x.id);
+//!
+//!         match user {
+//!             Some(id) if banned.0.contains(&id) => Flow::Stop,
+//!             _ => Flow::Continue,
+//!         }
+//!     });
+//! ```
+
+use crate::bot::RequestHandle;
+use crate::objects::Update;
+
+use std::{any::{Any, TypeId}, collections::HashMap, fmt, sync::Arc};
+
+use serde_json::Value;
+use failure::Error;
+
+/// Whether an update or request is passed on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Flow {
+    Continue,
+    /// Drops the update or fails the request
+    Stop,
+}
+
+/// Values of any type which middlewares attach to an update, at most one per type
+#[derive(Clone, Default)]
+pub struct Annotations {
+    values: HashMap<TypeId, Arc<dyn Any + Send + Sync>>,
+}
+
+impl Annotations {
+    /// Attaches a value, a previous value of the same type is replaced
+    pub fn insert<T: Any + Send + Sync>(&mut self, value: T) {
+        self.values.insert(TypeId::of::<T>(), Arc::new(value));
+    }
+
+    /// Returns the value of this type
+    pub fn get<T: Any + Send + Sync>(&self) -> Option<&T> {
+        self.values.get(&TypeId::of::<T>()).and_then(|x| x.downcast_ref())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+impl fmt::Debug for Annotations {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Annotations")
+            .field("len", &self.values.len())
+            .finish()
+    }
+}
+
+/// An update on its way to the handlers
+pub struct UpdateContext<'a> {
+    pub update: &'a mut Update,
+    /// The handle which the handlers will receive, e.g. to access the session
+    pub request: &'a RequestHandle,
+    pub annotations: &'a mut Annotations,
+}
+
+/// A request on its way to the Telegram server
+pub struct RequestContext<'a> {
+    /// The name of the Telegram function, e.g. `sendMessage`
+    pub function: &'static str,
+    /// The parameters of the function
+    pub body: &'a mut Value,
+}
+
+/// Runs around the dispatch of updates and around requests, every hook is optional
+pub trait Middleware: Send + Sync {
+    /// Called for every update before it is dispatched
+    fn on_update(&self, _ctx: &mut UpdateContext) -> Flow {
+        Flow::Continue
+    }
+
+    /// Called before a request is sent, a stopped request fails
+    fn on_request(&self, _ctx: &mut RequestContext) -> Flow {
+        Flow::Continue
+    }
+
+    /// Called with the result of a request, which is either the JSON encoded answer or an error
+    fn on_response(&self, _function: &'static str, _result: &Result<String, Error>) {}
+}
+
+impl<F> Middleware for F
+where
+    F: Fn(&mut UpdateContext) -> Flow + Send + Sync,
+{
+    fn on_update(&self, ctx: &mut UpdateContext) -> Flow {
+        self(ctx)
+    }
+}
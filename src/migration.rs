@@ -0,0 +1,61 @@
+//! Follows groups which have been upgraded to supergroups
+//!
+//! An upgraded group gets a new chat id. Requests to the old id fail with a
+//! `migrate_to_chat_id` parameter and both chats receive a service message with
+//! `migrate_to_chat_id` resp. `migrate_from_chat_id`. Each migration is reported once as a
+//! `ChatMigration`, so that applications can update their stored chat ids.
+
+use crate::bot::RequestHandle;
+use crate::objects::{Integer, Message};
+use crate::queue::HandlerSender;
+
+use std::{collections::HashSet, sync::{Arc, Mutex}};
+
+/// A group which has been upgraded to a supergroup with a new chat id
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ChatMigration {
+    pub from_chat_id: Integer,
+    pub to_chat_id: Integer,
+}
+
+impl ChatMigration {
+    /// Reads the migration from the service message of the old group or the new supergroup
+    pub fn from_message(msg: &Message) -> Option<ChatMigration> {
+        if let Some(to_chat_id) = msg.migrate_to_chat_id {
+            return Some(ChatMigration { from_chat_id: msg.chat.id, to_chat_id });
+        }
+
+        msg.migrate_from_chat_id
+            .map(|from_chat_id| ChatMigration { from_chat_id, to_chat_id: msg.chat.id })
+    }
+}
+
+/// Forwards every migration once to the stream returned by `Bot::migrations`
+#[derive(Clone)]
+pub struct MigrationNotifier {
+    sender: HandlerSender<(RequestHandle, ChatMigration)>,
+    seen: Arc<Mutex<HashSet<ChatMigration>>>,
+}
+
+impl MigrationNotifier {
+    pub fn new(sender: HandlerSender<(RequestHandle, ChatMigration)>) -> MigrationNotifier {
+        MigrationNotifier {
+            sender,
+            seen: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    /// Reports a migration once the stream has room, unless it was already reported before
+    pub async fn notify(&self, handle: &RequestHandle, migration: ChatMigration) {
+        if !self.seen.lock().unwrap().insert(migration) {
+            return;
+        }
+
+        info!("Chat {} migrated to {}", migration.from_chat_id, migration.to_chat_id);
+
+        self.sender
+            .send_when_room((handle.clone(), migration))
+            .await
+            .unwrap_or_else(|e| error!("Error: {}", e));
+    }
+}
@@ -15,6 +15,7 @@ use uuid::Uuid;
 
 #[derive(Deserialize, Debug)]
 #[serde(untagged)]
+#[allow(clippy::large_enum_variant)]
 pub enum EditResponse {
     Message(Message),
     Boolean(Boolean),
@@ -86,6 +87,7 @@ pub struct Message {
     pub location: Option<Location>,
     pub venue: Option<Venue>,
     pub new_chat_member: Option<User>,
+    pub new_chat_members: Option<Vec<User>>,
     pub left_chat_member: Option<User>,
     pub new_chat_title: Option<String>,
     pub new_chat_photo: Option<Vec<PhotoSize>>,
@@ -99,7 +101,7 @@ pub struct Message {
 }
 
 #[derive(Deserialize, Debug)]
-pub struct Messages(Vec<Message>);
+pub struct Messages(pub Vec<Message>);
 
 #[derive(Deserialize, Debug)]
 pub struct Updates(pub Vec<Update>);
@@ -112,10 +114,23 @@ pub struct Update {
     pub channel_post: Option<Message>,
     pub edited_channel_post: Option<Message>,
     pub inline_query: Option<InlineQuery>,
-    pub chosen_inline_result: Option<()>,
+    pub chosen_inline_result: Option<ChosenInlineResult>,
     pub callback_query: Option<CallbackQuery>,
 }
 
+/// Contains information about the current status of a webhook.
+#[derive(Deserialize, Debug)]
+pub struct WebhookInfo {
+    pub url: String,
+    pub has_custom_certificate: bool,
+    pub pending_update_count: Integer,
+    pub ip_address: Option<String>,
+    pub last_error_date: Option<Integer>,
+    pub last_error_message: Option<String>,
+    pub max_connections: Option<Integer>,
+    pub allowed_updates: Option<Vec<String>>,
+}
+
 /// This object represents one size of a photo or a file / sticker thumbnail.
 #[derive(Deserialize, Debug, Clone)]
 pub struct PhotoSize {
@@ -342,8 +357,28 @@ pub struct ChatMember {
     pub status: String,
 }
 
+/// This object represents a bot command shown in the menu of the Telegram client.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BotCommand {
+    pub command: String,
+    pub description: String,
+}
+
+/// This object represents the scope to which bot commands are applied.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BotCommandScope {
+    Default,
+    AllPrivateChats,
+    AllGroupChats,
+    AllChatAdministrators,
+    Chat { chat_id: Integer },
+    ChatAdministrators { chat_id: Integer },
+    ChatMember { chat_id: Integer, user_id: Integer },
+}
+
 /// Contains information about why a request was unsuccessfull.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct ResponseParameter {
     pub migrate_to_chat_id: Option<Integer>,
     pub retry_after: Option<Integer>,
@@ -819,7 +854,7 @@ pub mod input_message_content {
 pub struct ChosenInlineResult {
     pub result_id: String,
     pub from: User,
-    pub offset: String,
+    pub query: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub location: Option<Location>,
     #[serde(skip_serializing_if = "Option::is_none")]
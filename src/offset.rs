@@ -0,0 +1,153 @@
+//! Keeps the update offset across restarts and drops updates which were already handled
+//!
+//! Telegram delivers an update again until a later `getUpdates` request confirms it. If the bot
+//! stops between handling an update and confirming it, the update is handled twice after a
+//! restart. With an `OffsetStore` the bot remembers the offset of the next update itself:
+//!
+//! ```
+//! use telebot::offset::{FileOffsetStore, OffsetStore};
+//!
+//! let path = std::env::temp_dir().join("telebot-offset-doctest");
+//! let store = FileOffsetStore::new(&path);
+//!
+//! store.save(1234).unwrap();
+//! assert_eq!(store.load().unwrap(), Some(1234));
+//! # std::fs::remove_file(path).unwrap();
+//! ```
+
+use crate::error::ErrorKind;
+use crate::objects::Integer;
+
+use std::{fs, io, collections::{HashSet, VecDeque}, path::{Path, PathBuf}, sync::{Arc, Mutex}};
+
+use failure::{Error, Fail, ResultExt};
+
+/// The number of update ids which are remembered besides the offset
+const RECENT_UPDATES: usize = 1024;
+
+/// Stores the offset of the next update, which is one more than the id of the last handled one
+pub trait OffsetStore: Send + Sync {
+    /// Returns the stored offset or `None` if nothing was stored yet
+    fn load(&self) -> Result<Option<Integer>, Error>;
+
+    /// Replaces the stored offset
+    fn save(&self, offset: Integer) -> Result<(), Error>;
+}
+
+/// Stores the offset as a number in a text file
+#[derive(Clone, Debug)]
+pub struct FileOffsetStore {
+    path: PathBuf,
+}
+
+impl FileOffsetStore {
+    pub fn new<P: AsRef<Path>>(path: P) -> FileOffsetStore {
+        FileOffsetStore { path: path.as_ref().to_path_buf() }
+    }
+}
+
+impl OffsetStore for FileOffsetStore {
+    fn load(&self) -> Result<Option<Integer>, Error> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(Error::from(err.context(ErrorKind::OffsetStore))),
+        };
+
+        let offset = content.trim().parse::<Integer>().context(ErrorKind::OffsetStore)?;
+
+        Ok(Some(offset))
+    }
+
+    fn save(&self, offset: Integer) -> Result<(), Error> {
+        // the new offset is renamed over the old one, so a crash never leaves a truncated file
+        let tmp = self.path.with_extension("tmp");
+
+        fs::write(&tmp, offset.to_string()).context(ErrorKind::OffsetStore)?;
+        fs::rename(&tmp, &self.path).context(ErrorKind::OffsetStore)?;
+
+        Ok(())
+    }
+}
+
+/// Keeps the offset in memory, e.g. for tests. All clones share the same offset.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryOffsetStore {
+    offset: Arc<Mutex<Option<Integer>>>,
+}
+
+impl MemoryOffsetStore {
+    pub fn new() -> MemoryOffsetStore {
+        MemoryOffsetStore::default()
+    }
+}
+
+impl OffsetStore for MemoryOffsetStore {
+    fn load(&self) -> Result<Option<Integer>, Error> {
+        Ok(*self.offset.lock().unwrap())
+    }
+
+    fn save(&self, offset: Integer) -> Result<(), Error> {
+        *self.offset.lock().unwrap() = Some(offset);
+
+        Ok(())
+    }
+}
+
+/// Remembers the handled updates and drops those which are delivered again
+///
+/// Updates before the initial offset were handled before a restart and are always dropped. The
+/// ids of the last updates are kept as well, so that updates which arrive out of order, e.g. from
+/// a webhook with several connections, are still handled once. All clones share the same state.
+#[derive(Clone)]
+pub struct UpdateGuard {
+    state: Arc<Mutex<GuardState>>,
+}
+
+struct GuardState {
+    floor: Integer,
+    offset: Integer,
+    recent: HashSet<Integer>,
+    order: VecDeque<Integer>,
+}
+
+impl UpdateGuard {
+    /// Creates a guard which drops all updates before `offset`
+    pub fn new(offset: Integer) -> UpdateGuard {
+        UpdateGuard {
+            state: Arc::new(Mutex::new(GuardState {
+                floor: offset,
+                offset,
+                recent: HashSet::new(),
+                order: VecDeque::new(),
+            })),
+        }
+    }
+
+    /// Marks an update as handled, returns false if it was handled before
+    pub fn check(&self, update_id: Integer) -> bool {
+        let mut state = self.state.lock().unwrap();
+
+        if update_id < state.floor || !state.recent.insert(update_id) {
+            return false;
+        }
+
+        state.order.push_back(update_id);
+        state.offset = state.offset.max(update_id + 1);
+
+        // forgotten updates are treated like those before a restart
+        if state.order.len() > RECENT_UPDATES {
+            if let Some(id) = state.order.pop_front() {
+                state.recent.remove(&id);
+                state.floor = state.floor.max(id + 1);
+            }
+        }
+
+        true
+    }
+
+    /// The offset of the next update, one more than the highest handled id
+    pub fn offset(&self) -> Integer {
+        self.state.lock().unwrap().offset
+    }
+}
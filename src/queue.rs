@@ -0,0 +1,516 @@
+//! Bounded handler queues and the order in which updates are processed
+//!
+//! Every stream of a bot, like the one returned by `new_cmd`, is fed by a queue with a limited
+//! capacity. If a handler falls behind and its queue is full, a warning is logged and the next
+//! update for this handler waits until the queue has room again, so that unprocessed updates stay
+//! with Telegram instead of piling up in memory. Meanwhile no further updates are requested, or,
+//! with a webhook, the pending requests of Telegram are answered later. Updates for other handlers
+//! aren't held back by a full queue. The queues of a bot can be inspected with
+//! its `Queues` handle:
+//!
+//! ```
+//! use telebot::Bot;
+//! use telebot::queue::Processing;
+//!
+//! let mut bot = Bot::new("TOKEN")
+//!     .handler_capacity(16)
+//!     .processing(Processing::SequentialPerChat);
+//!
+//! let start = bot.new_cmd("/start");
+//! let queues = bot.queues();
+//!
+//! for stats in queues.stats() {
+//!     println!("{}: {} of {} waiting, full {} times", stats.name, stats.len, stats.capacity, stats.saturated);
+//! }
+//! ```
+//!
+//! By default updates are processed concurrently, so two messages of the same chat may be handled
+//! at the same time if they reach different streams. With `Processing::SequentialPerChat` an update
+//! is only dispatched once the previous update of its chat was handled, that is when the last
+//! clone of its `RequestHandle` was dropped. Handlers can still work on different chats at the
+//! same time, e.g. with `try_for_each_concurrent`.
+
+use crate::objects::{Integer, Update};
+use crate::session::{SessionKey, SessionScope};
+
+use std::{pin::Pin, collections::{HashMap, VecDeque}, sync::{Arc, Mutex}, task::{Context, Poll}};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+
+use futures::{Stream, StreamExt, channel::mpsc::{self, TrySendError, UnboundedReceiver, UnboundedSender}};
+use tokio::sync::Notify;
+
+/// The capacity of every handler queue unless `Bot::handler_capacity` is set
+pub const DEFAULT_CAPACITY: usize = 100;
+
+/// How the updates of a chat are ordered
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Processing {
+    /// Updates are dispatched as soon as they arrive
+    Concurrent,
+    /// An update waits until the previous update of its chat was handled
+    SequentialPerChat,
+}
+
+/// A snapshot of a handler queue
+#[derive(Clone, Debug)]
+pub struct QueueStats {
+    /// The stream which the queue feeds, e.g. `/start` or `callback queries`
+    pub name: String,
+    pub capacity: usize,
+    /// The number of updates which wait for the handler
+    pub len: usize,
+    /// The highest number of waiting updates so far
+    pub max_len: usize,
+    /// The number of updates which were put into the queue
+    pub received: u64,
+    /// How often the queue became full
+    pub saturated: u64,
+}
+
+struct QueueState {
+    name: String,
+    capacity: Arc<AtomicUsize>,
+    len: AtomicUsize,
+    max_len: AtomicUsize,
+    received: AtomicU64,
+    saturated: AtomicU64,
+    full: AtomicBool,
+    closed: AtomicBool,
+    room: Notify,
+}
+
+impl QueueState {
+    fn is_full(&self) -> bool {
+        !self.closed.load(Ordering::SeqCst) && self.len.load(Ordering::SeqCst) >= self.capacity.load(Ordering::SeqCst)
+    }
+
+    fn put(&self) {
+        let len = self.len.fetch_add(1, Ordering::SeqCst) + 1;
+        let capacity = self.capacity.load(Ordering::SeqCst);
+
+        self.received.fetch_add(1, Ordering::SeqCst);
+        self.max_len.fetch_max(len, Ordering::SeqCst);
+
+        // warn once each time the queue fills up
+        if len >= capacity && !self.full.swap(true, Ordering::SeqCst) {
+            self.saturated.fetch_add(1, Ordering::SeqCst);
+
+            warn!("The queue of {} is full with {} updates, new updates wait until the handler catches up", self.name, len);
+        }
+    }
+
+    fn take(&self) {
+        let len = self.len.fetch_sub(1, Ordering::SeqCst) - 1;
+
+        if len < self.capacity.load(Ordering::SeqCst) {
+            self.full.store(false, Ordering::SeqCst);
+        }
+
+        // both the update loop and the released chats may wait for room
+        self.room.notify_waiters();
+    }
+
+    /// Reverts `put` for an update which couldn't be sent
+    fn unput(&self) {
+        self.received.fetch_sub(1, Ordering::SeqCst);
+        self.take();
+    }
+
+    fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        self.room.notify_waiters();
+    }
+
+    async fn wait_for_room(&self) {
+        loop {
+            // created before the check, so that no wakeup is missed
+            let room = self.room.notified();
+
+            if !self.is_full() {
+                return;
+            }
+
+            debug!("Waiting for room in the queue of {}", self.name);
+
+            room.await;
+        }
+    }
+
+    fn stats(&self) -> QueueStats {
+        QueueStats {
+            name: self.name.clone(),
+            capacity: self.capacity.load(Ordering::SeqCst),
+            len: self.len.load(Ordering::SeqCst),
+            max_len: self.max_len.load(Ordering::SeqCst),
+            received: self.received.load(Ordering::SeqCst),
+            saturated: self.saturated.load(Ordering::SeqCst),
+        }
+    }
+}
+
+/// A clonable handle to the handler queues of a bot
+#[derive(Clone)]
+pub struct Queues {
+    capacity: Arc<AtomicUsize>,
+    states: Arc<Mutex<Vec<Arc<QueueState>>>>,
+}
+
+impl Default for Queues {
+    fn default() -> Queues {
+        Queues::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl Queues {
+    pub fn new(capacity: usize) -> Queues {
+        Queues {
+            capacity: Arc::new(AtomicUsize::new(capacity.max(1))),
+            states: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// The capacity of every queue
+    pub fn capacity(&self) -> usize {
+        self.capacity.load(Ordering::SeqCst)
+    }
+
+    /// Changes the capacity of all queues, including the existing ones
+    pub fn set_capacity(&self, capacity: usize) {
+        self.capacity.store(capacity.max(1), Ordering::SeqCst);
+
+        for state in self.states.lock().unwrap().iter() {
+            state.room.notify_waiters();
+        }
+    }
+
+    /// Returns a snapshot of every queue whose stream wasn't dropped
+    pub fn stats(&self) -> Vec<QueueStats> {
+        self.states.lock().unwrap().iter()
+            .filter(|x| !x.closed.load(Ordering::SeqCst))
+            .map(|x| x.stats())
+            .collect()
+    }
+
+    /// Creates a new queue, the stream is named after `name` in the stats
+    pub(crate) fn channel<T>(&self, name: &str) -> (HandlerSender<T>, HandlerStream<T>) {
+        let (sender, receiver) = mpsc::unbounded();
+
+        let state = Arc::new(QueueState {
+            name: name.into(),
+            capacity: self.capacity.clone(),
+            len: AtomicUsize::new(0),
+            max_len: AtomicUsize::new(0),
+            received: AtomicU64::new(0),
+            saturated: AtomicU64::new(0),
+            full: AtomicBool::new(false),
+            closed: AtomicBool::new(false),
+            room: Notify::new(),
+        });
+
+        let mut states = self.states.lock().unwrap();
+
+        states.retain(|x| !x.closed.load(Ordering::SeqCst));
+        states.push(state.clone());
+
+        (HandlerSender { sender, state: state.clone() }, HandlerStream { receiver, state })
+    }
+}
+
+/// Puts updates into the queue of a stream
+///
+/// The bot sends with `send_when_room`, so that the capacity is kept. `send` never blocks and may
+/// exceed the capacity.
+pub struct HandlerSender<T> {
+    sender: UnboundedSender<T>,
+    state: Arc<QueueState>,
+}
+
+impl<T> Clone for HandlerSender<T> {
+    fn clone(&self) -> HandlerSender<T> {
+        HandlerSender {
+            sender: self.sender.clone(),
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<T> HandlerSender<T> {
+    /// Puts an update into the queue, fails if the stream was dropped
+    pub fn send(&self, item: T) -> Result<(), TrySendError<T>> {
+        // counted before, so that the stream never takes an update which wasn't counted yet
+        self.state.put();
+
+        self.sender.unbounded_send(item).inspect_err(|_| self.state.unput())
+    }
+
+    /// Waits until the queue has room and puts an update into it, fails if the stream was dropped
+    pub async fn send_when_room(&self, item: T) -> Result<(), TrySendError<T>> {
+        self.state.wait_for_room().await;
+
+        self.send(item)
+    }
+
+    /// Returns true if the queue reached its capacity
+    pub fn is_full(&self) -> bool {
+        self.state.is_full()
+    }
+}
+
+/// Takes updates from the queue of a stream
+pub(crate) struct HandlerStream<T> {
+    receiver: UnboundedReceiver<T>,
+    state: Arc<QueueState>,
+}
+
+impl<T> Stream for HandlerStream<T> {
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<T>> {
+        let item = self.receiver.poll_next_unpin(cx);
+
+        if let Poll::Ready(Some(_)) = item {
+            self.state.take();
+        }
+
+        item
+    }
+}
+
+impl<T> Drop for HandlerStream<T> {
+    fn drop(&mut self) {
+        self.state.close();
+    }
+}
+
+/// Returns the chat of an update, updates without a chat are never held back
+pub(crate) fn chat_of(update: &Update) -> Option<Integer> {
+    match SessionKey::of(update, SessionScope::Chat) {
+        Some(SessionKey::Chat(id)) => Some(id),
+        _ => None,
+    }
+}
+
+/// Holds back the updates of chats whose previous update is still handled
+pub(crate) struct Lanes {
+    pending: Mutex<HashMap<Integer, VecDeque<Update>>>,
+    released: UnboundedSender<Integer>,
+}
+
+impl Lanes {
+    /// Creates the lanes and the receiver of chats which finished an update
+    pub(crate) fn new() -> (Arc<Lanes>, UnboundedReceiver<Integer>) {
+        let (released, receiver) = mpsc::unbounded();
+
+        let lanes = Lanes {
+            pending: Mutex::new(HashMap::new()),
+            released,
+        };
+
+        (Arc::new(lanes), receiver)
+    }
+
+    /// Returns the update with the turn of its chat, or keeps it until the chat is released
+    pub(crate) fn enter(&self, chat: Integer, update: Update) -> Option<(Update, ChatTurn)> {
+        let mut pending = self.pending.lock().unwrap();
+
+        match pending.get_mut(&chat) {
+            Some(waiting) => {
+                waiting.push_back(update);
+
+                None
+            }
+            None => {
+                pending.insert(chat, VecDeque::new());
+
+                Some((update, self.turn(chat)))
+            }
+        }
+    }
+
+    /// Returns the next update of a released chat, the chat is idle if none is left
+    pub(crate) fn next(&self, chat: Integer) -> Option<(Update, ChatTurn)> {
+        let mut pending = self.pending.lock().unwrap();
+
+        match pending.get_mut(&chat).and_then(|x| x.pop_front()) {
+            Some(update) => Some((update, self.turn(chat))),
+            None => {
+                pending.remove(&chat);
+
+                None
+            }
+        }
+    }
+
+    /// The id of the oldest held back update, the offset must not be saved beyond it
+    pub(crate) fn oldest(&self) -> Option<Integer> {
+        self.pending.lock().unwrap()
+            .values()
+            .filter_map(|waiting| waiting.front().map(|x| x.update_id))
+            .min()
+    }
+
+    /// Removes all held back updates, in the order they arrived per chat
+    pub(crate) fn drain(&self) -> Vec<Update> {
+        self.pending.lock().unwrap()
+            .drain()
+            .flat_map(|(_, waiting)| waiting)
+            .collect()
+    }
+
+    fn turn(&self, chat: Integer) -> ChatTurn {
+        ChatTurn {
+            chat,
+            released: self.released.clone(),
+        }
+    }
+}
+
+/// Releases the chat of an update when the update was handled
+pub(crate) struct ChatTurn {
+    chat: Integer,
+    released: UnboundedSender<Integer>,
+}
+
+impl Drop for ChatTurn {
+    fn drop(&mut self) {
+        // fails only after the update loop ended
+        let _ = self.released.unbounded_send(self.chat);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::FutureExt;
+
+    fn update(update_id: Integer, chat: Integer) -> Update {
+        serde_json::from_value(serde_json::json!({
+            "update_id": update_id,
+            "message": {"message_id": update_id, "date": 0, "chat": {"id": chat, "type": "private"}}
+        })).unwrap()
+    }
+
+    #[test]
+    fn counts_the_waiting_updates() {
+        let queues = Queues::new(2);
+        let (sender, mut stream) = queues.channel::<u32>("/start");
+
+        sender.send(1).unwrap();
+        sender.send(2).unwrap();
+        assert!(sender.is_full());
+
+        assert_eq!(stream.next().now_or_never(), Some(Some(1)));
+        assert!(!sender.is_full());
+
+        let stats = &queues.stats()[0];
+        assert_eq!((stats.len, stats.max_len, stats.received), (1, 2, 2));
+    }
+
+    #[test]
+    fn counts_each_time_the_queue_fills_up() {
+        let queues = Queues::new(1);
+        let (sender, mut stream) = queues.channel::<u32>("/start");
+
+        sender.send(1).unwrap();
+        sender.send(2).unwrap();
+        assert_eq!(queues.stats()[0].saturated, 1);
+
+        stream.next().now_or_never();
+        stream.next().now_or_never();
+        sender.send(3).unwrap();
+        assert_eq!(queues.stats()[0].saturated, 2);
+    }
+
+    #[test]
+    fn failed_sends_are_not_counted() {
+        let queues = Queues::new(1);
+        let (sender, mut stream) = queues.channel::<u32>("/start");
+
+        stream.receiver.close();
+        assert!(sender.send(1).is_err());
+
+        let stats = sender.state.stats();
+        assert_eq!((stats.len, stats.received), (0, 0));
+        assert!(!sender.is_full());
+    }
+
+    #[test]
+    fn wakes_every_waiter() {
+        let queues = Queues::new(1);
+        let (sender, mut stream) = queues.channel::<u32>("/start");
+
+        sender.send(1).unwrap();
+
+        let mut first = Box::pin(sender.state.wait_for_room());
+        let mut second = Box::pin(sender.state.wait_for_room());
+        assert!(first.as_mut().now_or_never().is_none());
+        assert!(second.as_mut().now_or_never().is_none());
+
+        stream.next().now_or_never();
+
+        assert!(first.now_or_never().is_some());
+        assert!(second.now_or_never().is_some());
+    }
+
+    #[test]
+    fn waits_only_for_its_own_queue() {
+        let queues = Queues::new(1);
+        let (start, mut starts) = queues.channel::<u32>("/start");
+        let (stop, _stops) = queues.channel::<u32>("/stop");
+
+        start.send(1).unwrap();
+
+        let mut waiting = Box::pin(start.send_when_room(2));
+        assert!(waiting.as_mut().now_or_never().is_none());
+        assert!(stop.send_when_room(3).now_or_never().unwrap().is_ok());
+
+        starts.next().now_or_never();
+        assert!(waiting.now_or_never().unwrap().is_ok());
+    }
+
+    #[test]
+    fn holds_back_the_updates_of_a_busy_chat() {
+        let (lanes, mut released) = Lanes::new();
+
+        let (first, turn) = lanes.enter(1, update(10, 1)).unwrap();
+        assert_eq!(first.update_id, 10);
+
+        assert!(lanes.enter(1, update(11, 1)).is_none());
+        assert!(lanes.enter(1, update(12, 1)).is_none());
+        let _other = lanes.enter(2, update(13, 2)).unwrap();
+
+        // the chat is released when the turn is dropped
+        assert!(released.next().now_or_never().is_none());
+        drop(turn);
+        assert_eq!(released.next().now_or_never(), Some(Some(1)));
+
+        let (second, turn) = lanes.next(1).unwrap();
+        assert_eq!(second.update_id, 11);
+        drop(turn);
+
+        let (third, turn) = lanes.next(1).unwrap();
+        assert_eq!(third.update_id, 12);
+        drop(turn);
+
+        // the chat is idle again, so the next update passes at once
+        assert!(lanes.next(1).is_none());
+        assert!(lanes.enter(1, update(14, 1)).is_some());
+    }
+
+    #[test]
+    fn oldest_held_back_update() {
+        let (lanes, _released) = Lanes::new();
+
+        let _first = lanes.enter(1, update(10, 1)).unwrap();
+        let _second = lanes.enter(2, update(11, 2)).unwrap();
+        assert_eq!(lanes.oldest(), None);
+
+        assert!(lanes.enter(2, update(13, 2)).is_none());
+        assert!(lanes.enter(1, update(12, 1)).is_none());
+        assert_eq!(lanes.oldest(), Some(12));
+
+        let _next = lanes.next(1).unwrap();
+        assert_eq!(lanes.oldest(), Some(13));
+    }
+}
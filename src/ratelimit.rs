@@ -0,0 +1,184 @@
+//! Throttles outgoing messages to stay within the limits of Telegram
+//!
+//! Telegram allows about 30 messages per second in total, one message per second to the same
+//! private chat and 20 messages per minute to the same group. The `RateLimiter` keeps a token
+//! bucket for each of them and delays requests until all buckets have a free token, instead of
+//! letting Telegram answer with error 429. Only functions which send or edit a message and have a
+//! `chat_id` property are throttled, other requests like getChatMember don't use up the limits.
+
+use std::{cmp, collections::HashMap, sync::{Arc, Mutex}, time::{Duration, Instant}};
+
+use serde_json::value::Value;
+use tokio::time;
+
+/// The number of messages which can be sent in a time span
+#[derive(Clone, Copy, Debug)]
+pub struct Limit {
+    pub count: u32,
+    pub per: Duration,
+}
+
+impl Limit {
+    pub fn new(count: u32, per: Duration) -> Limit {
+        Limit { count: cmp::max(count, 1), per }
+    }
+
+    fn interval(&self) -> Duration {
+        self.per / self.count
+    }
+
+    /// The time a full bucket allows to send in advance of the steady rate
+    fn tolerance(&self) -> Duration {
+        self.interval() * (self.count - 1)
+    }
+}
+
+/// A token bucket implemented as generic cell rate algorithm, `tat` is the theoretical arrival
+/// time of the next request
+#[derive(Clone, Copy)]
+struct Bucket {
+    tat: Instant,
+}
+
+impl Bucket {
+    fn new(now: Instant) -> Bucket {
+        Bucket { tat: now }
+    }
+
+    /// The earliest time at which the next request conforms to the limit
+    fn earliest(&self, limit: &Limit, now: Instant) -> Instant {
+        if self.tat > now + limit.tolerance() {
+            self.tat - limit.tolerance()
+        } else {
+            now
+        }
+    }
+
+    fn take(&mut self, limit: &Limit, at: Instant) {
+        self.tat = cmp::max(self.tat, at) + limit.interval();
+    }
+}
+
+struct State {
+    global: Bucket,
+    chats: HashMap<String, Bucket>,
+}
+
+/// Schedules outgoing requests within the global and per chat limits
+///
+/// The limiter can be cloned, all clones share the same buckets.
+#[derive(Clone)]
+pub struct RateLimiter {
+    global: Limit,
+    private_chat: Limit,
+    group: Limit,
+    state: Arc<Mutex<State>>,
+}
+
+impl Default for RateLimiter {
+    fn default() -> RateLimiter {
+        RateLimiter::new()
+    }
+}
+
+impl RateLimiter {
+    /// Creates a limiter with the default limits of Telegram
+    pub fn new() -> RateLimiter {
+        RateLimiter {
+            global: Limit::new(30, Duration::from_secs(1)),
+            private_chat: Limit::new(1, Duration::from_secs(1)),
+            group: Limit::new(20, Duration::from_secs(60)),
+            state: Arc::new(Mutex::new(State {
+                global: Bucket::new(Instant::now()),
+                chats: HashMap::new(),
+            })),
+        }
+    }
+
+    /// Sets the number of messages to all chats together
+    pub fn global(mut self, limit: Limit) -> RateLimiter {
+        self.global = limit;
+
+        self
+    }
+
+    /// Sets the number of messages to a single private chat
+    pub fn private_chat(mut self, limit: Limit) -> RateLimiter {
+        self.private_chat = limit;
+
+        self
+    }
+
+    /// Sets the number of messages to a single group, supergroup or channel
+    pub fn group(mut self, limit: Limit) -> RateLimiter {
+        self.group = limit;
+
+        self
+    }
+
+    /// Reserves a slot of the chat bucket and returns the time the message can be sent at
+    fn reserve_chat(&self, chat_id: &Value) -> Instant {
+        // private chats have positive identifiers, groups and channels negative ones or a name
+        let (key, limit) = match chat_id {
+            Value::Number(id) if id.as_i64().map(|x| x > 0).unwrap_or(false) => (id.to_string(), &self.private_chat),
+            Value::String(name) => (name.clone(), &self.group),
+            id => (id.to_string(), &self.group),
+        };
+
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+
+        if state.chats.len() > 1024 {
+            state.chats.retain(|_, bucket| bucket.tat > now);
+        }
+
+        let chat = state.chats.entry(key).or_insert_with(|| Bucket::new(now));
+        let at = chat.earliest(limit, now);
+        chat.take(limit, at);
+
+        at
+    }
+
+    /// Reserves a slot of the global bucket. This happens only after the chat slot is reached, so
+    /// that a message waiting for a busy chat doesn't hold back messages to other chats.
+    fn reserve_global(&self) -> Instant {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+
+        let at = state.global.earliest(&self.global, now);
+        state.global.take(&self.global, at);
+
+        at
+    }
+
+    /// Returns true if the function sends a message and counts against the limits
+    pub fn limits(func: &str) -> bool {
+        match func {
+            "sendChatAction" => false,
+            _ => ["send", "forward", "copy", "edit"].iter().any(|x| func.starts_with(x)),
+        }
+    }
+
+    /// Resolves once the message can be sent. Other functions and messages without a `chat_id`
+    /// are not throttled.
+    pub async fn wait(&self, func: &str, msg: &Value) {
+        let chat_at = match msg.get("chat_id") {
+            Some(chat_id) if RateLimiter::limits(func) => self.reserve_chat(chat_id),
+            _ => return,
+        };
+
+        sleep_until(chat_at).await;
+        sleep_until(self.reserve_global()).await;
+    }
+}
+
+/// Waits until `at`, the timer is only started if this lies in the future
+async fn sleep_until(at: Instant) {
+    if at <= Instant::now() {
+        return;
+    }
+
+    debug!("Delay message by {:?}", at - Instant::now());
+
+    time::sleep_until(at.into()).await;
+}
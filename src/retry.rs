@@ -0,0 +1,156 @@
+//! Repeats requests which failed because of flood control or a transient network error
+//!
+//! Telegram answers with error 429 and a `retry_after` parameter if a bot sends too many requests.
+//! With a `RetryPolicy` the `RequestHandle` waits for the given number of seconds and sends the
+//! request again, instead of returning the error.
+
+use crate::error::{ErrorKind, TelegramError};
+use crate::file::File;
+
+use std::{io::{Cursor, Read}, time::Duration};
+
+use failure::{Context, Error, ResultExt};
+use futures_retry::{ErrorHandler, RetryPolicy as Retry};
+
+/// Decides which failed requests are sent again
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    network_errors: bool,
+    network_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Sends a request at most `max_attempts` times, including the first attempt
+    pub fn new(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            network_errors: true,
+            network_delay: Duration::from_secs(1),
+        }
+    }
+
+    /// Also repeat requests which failed because of a network error or an internal error of the
+    /// Telegram server, enabled by default
+    pub fn network_errors(mut self, retry: bool) -> RetryPolicy {
+        self.network_errors = retry;
+
+        self
+    }
+
+    /// Sets the time to wait before a request is repeated after a network error
+    pub fn network_delay(mut self, delay: Duration) -> RetryPolicy {
+        self.network_delay = delay;
+
+        self
+    }
+
+    /// Creates the error handler for a single request
+    pub fn handler(&self, func: &'static str) -> RetryHandler {
+        RetryHandler {
+            policy: self.clone(),
+            func,
+        }
+    }
+}
+
+/// Decides whether a single request is repeated and how long to wait after an error
+pub struct RetryHandler {
+    policy: RetryPolicy,
+    func: &'static str,
+}
+
+impl RetryHandler {
+    fn delay(&self, err: &Error) -> Option<Duration> {
+        if let Some(err) = TelegramError::find(err) {
+            if let Some(secs) = err.retry_after() {
+                return Some(Duration::from_secs(secs.max(0) as u64));
+            }
+
+            if self.policy.network_errors && err.error_code().map(|x| x >= 500).unwrap_or(false) {
+                return Some(self.policy.network_delay);
+            }
+
+            return None;
+        }
+
+        let is_network_error = err.iter_chain()
+            .filter_map(|x| x.downcast_ref::<Context<ErrorKind>>())
+            .any(|x| *x.get_context() == ErrorKind::Hyper);
+
+        if self.policy.network_errors && is_network_error {
+            Some(self.policy.network_delay)
+        } else {
+            None
+        }
+    }
+}
+
+impl ErrorHandler<Error> for RetryHandler {
+    type OutError = Error;
+
+    fn handle(&mut self, attempt: usize, err: Error) -> Retry<Error> {
+        if attempt >= self.policy.max_attempts as usize {
+            return Retry::ForwardError(err);
+        }
+
+        match self.delay(&err) {
+            Some(delay) => {
+                warn!("Request {} failed ({}), retrying in {:?}", self.func, err, delay);
+
+                Retry::WaitRetry(delay)
+            }
+            None => Retry::ForwardError(err),
+        }
+    }
+}
+
+/// A list of files which can be sent several times. The content of in-memory files is read
+/// once on the blocking thread pool and kept in a buffer.
+pub struct ReplayableFiles(Vec<ReplayableFile>);
+
+enum ReplayableFile {
+    Memory { name: String, content: Vec<u8> },
+    Disk(std::path::PathBuf),
+    Telegram(String),
+    Url(String),
+}
+
+impl ReplayableFiles {
+    pub async fn new(files: Vec<File>) -> Result<ReplayableFiles, Error> {
+        let mut replayable = Vec::new();
+
+        for file in files {
+            replayable.push(match file {
+                File::Memory { name, mut source } => {
+                    let content = tokio::task::spawn_blocking(move || {
+                        let mut content = Vec::new();
+                        source.read_to_end(&mut content).map(|_| content)
+                    });
+
+                    let content = content.await.context(ErrorKind::Tokio)?.context(ErrorKind::TelegramFileRead)?;
+
+                    ReplayableFile::Memory { name, content }
+                }
+                File::Disk { path } => ReplayableFile::Disk(path),
+                File::Telegram(id) => ReplayableFile::Telegram(id),
+                File::Url(url) => ReplayableFile::Url(url),
+            });
+        }
+
+        Ok(ReplayableFiles(replayable))
+    }
+
+    /// Creates a new set of files for the next attempt
+    pub fn files(&self) -> Vec<File> {
+        self.0.iter().map(|file| match file {
+            ReplayableFile::Memory { name, content } => File::Memory {
+                name: name.clone(),
+                source: Box::new(Cursor::new(content.clone())),
+            },
+            ReplayableFile::Disk(path) => File::Disk { path: path.clone() },
+            ReplayableFile::Telegram(id) => File::Telegram(id.clone()),
+            ReplayableFile::Url(url) => File::Url(url.clone()),
+        }).collect()
+    }
+}
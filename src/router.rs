@@ -0,0 +1,96 @@
+//! Routes messages by regular expressions and predicates
+//!
+//! A `Route` is checked against every message which isn't handled by a command registered with
+//! `new_cmd`. Routes with a higher priority are checked first, routes with the same priority in
+//! the order they were registered. The message is sent to the first matching route:
+//!
+//! ```
+//! use telebot::Bot;
+//! use telebot::router::Route;
+//!
+//! let mut bot = Bot::new("TOKEN");
+//!
+//! let greetings = bot.route(Route::regex(r"(?i)^(hi|hello)\b").unwrap());
+//! let replies = bot.route(Route::predicate(|msg| msg.reply_to_message.is_some()).priority(10));
+//! ```
+
+use crate::error::ErrorKind;
+use crate::objects::Message;
+
+use std::{fmt, sync::Arc};
+
+use regex::Regex;
+use failure::{Error, ResultExt};
+
+#[derive(Clone)]
+enum Matcher {
+    Regex(Regex),
+    Predicate(Arc<dyn Fn(&Message) -> bool + Send + Sync>),
+}
+
+/// Selects messages by a regular expression or a predicate
+#[derive(Clone)]
+pub struct Route {
+    matcher: Matcher,
+    priority: i32,
+}
+
+impl Route {
+    /// Matches the text or the caption of a message against a regular expression
+    pub fn regex(pattern: &str) -> Result<Route, Error> {
+        let regex = Regex::new(pattern).context(ErrorKind::Regex)?;
+
+        Ok(Route {
+            matcher: Matcher::Regex(regex),
+            priority: 0,
+        })
+    }
+
+    /// Matches all messages for which the predicate returns true
+    pub fn predicate<F>(predicate: F) -> Route
+    where
+        F: Fn(&Message) -> bool + Send + Sync + 'static,
+    {
+        Route {
+            matcher: Matcher::Predicate(Arc::new(predicate)),
+            priority: 0,
+        }
+    }
+
+    /// Sets the priority of the route, the default is zero
+    pub fn priority(mut self, priority: i32) -> Route {
+        self.priority = priority;
+
+        self
+    }
+
+    /// The priority of the route
+    pub fn get_priority(&self) -> i32 {
+        self.priority
+    }
+
+    /// Checks whether the message is selected by this route
+    pub fn matches(&self, msg: &Message) -> bool {
+        match self.matcher {
+            Matcher::Regex(ref regex) => msg.text.as_ref()
+                .or(msg.caption.as_ref())
+                .map(|text| regex.is_match(text))
+                .unwrap_or(false),
+            Matcher::Predicate(ref predicate) => predicate(msg),
+        }
+    }
+}
+
+impl fmt::Debug for Route {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let matcher = match self.matcher {
+            Matcher::Regex(ref regex) => format!("Regex({})", regex),
+            Matcher::Predicate(_) => "Predicate".into(),
+        };
+
+        f.debug_struct("Route")
+            .field("matcher", &matcher)
+            .field("priority", &self.priority)
+            .finish()
+    }
+}
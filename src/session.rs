@@ -0,0 +1,399 @@
+//! Data which is kept per chat or per user across updates
+//!
+//! With a `SessionStore` every dispatched update loads the session of its chat or user. Handlers
+//! get it from the `RequestHandle` and read or write named values, which are serialized with
+//! serde. The session is saved once the update is handled, that is when the last clone of its
+//! `RequestHandle` is dropped. The bot loads and saves sessions on the blocking thread pool, the
+//! saves of a session are written in the order the updates finished.
+//!
+//! Every update gets its own copy of the session. If two updates of the same chat or user are
+//! handled at the same time, the changes of the one which finishes first are overwritten. Process
+//! the updates with `Processing::SequentialPerChat` to avoid this with a chat scope:
+//!
+//! ```
+//! use telebot::Bot;
+//! use telebot::session::{FileSessionStore, SessionScope};
+//!
+//! let bot = Bot::new("TOKEN")
+//!     .session_store(FileSessionStore::new("sessions"))
+//!     .session_scope(SessionScope::User);
+//! ```
+//!
+//! Sessions can be used without a bot as well:
+//!
+//! ```
+//! use telebot::session::{MemorySessionStore, Session, SessionKey, SessionStore};
+//!
+//! let store = MemorySessionStore::new();
+//!
+//! let session = Session::load(SessionKey::Chat(42), store.clone()).unwrap();
+//! let count: u32 = session.get("count").unwrap().unwrap_or(0);
+//! session.set("count", count + 1).unwrap();
+//! drop(session);
+//!
+//! let session = Session::load(SessionKey::Chat(42), store).unwrap();
+//! assert_eq!(session.get::<u32>("count").unwrap(), Some(1));
+//! ```
+
+use crate::error::ErrorKind;
+use crate::objects::{Integer, Update};
+
+use std::{fmt, fs, io, collections::HashMap, path::{Path, PathBuf}, sync::{Arc, Mutex, atomic::{AtomicU64, Ordering}}};
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{self, Map, Value};
+use failure::{Error, Fail, ResultExt};
+use uuid::Uuid;
+
+/// Whether sessions belong to chats or to users
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SessionScope {
+    Chat,
+    User,
+}
+
+/// Identifies a session
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SessionKey {
+    Chat(Integer),
+    User(Integer),
+}
+
+impl SessionKey {
+    /// Returns the key of the chat or of the user who sent the update
+    pub fn of(update: &Update, scope: SessionScope) -> Option<SessionKey> {
+        let message = update.message.as_ref()
+            .or(update.edited_message.as_ref())
+            .or(update.channel_post.as_ref())
+            .or(update.edited_channel_post.as_ref())
+            .or(update.callback_query.as_ref().and_then(|x| x.message.as_ref()));
+
+        match scope {
+            SessionScope::Chat => message.map(|x| SessionKey::Chat(x.chat.id)),
+            SessionScope::User => {
+                let user = update.callback_query.as_ref().map(|x| &x.from)
+                    .or(update.inline_query.as_ref().map(|x| &x.from))
+                    .or(update.chosen_inline_result.as_ref().map(|x| &x.from))
+                    .or(message.and_then(|x| x.from.as_ref()));
+
+                user.map(|x| SessionKey::User(x.id))
+            }
+        }
+    }
+}
+
+impl fmt::Display for SessionKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SessionKey::Chat(id) => write!(f, "chat_{}", id),
+            SessionKey::User(id) => write!(f, "user_{}", id),
+        }
+    }
+}
+
+/// Stores the values of all sessions as JSON objects
+pub trait SessionStore: Send + Sync {
+    /// Returns the stored session or `None` if nothing was stored yet
+    fn load(&self, key: SessionKey) -> Result<Option<Map<String, Value>>, Error>;
+
+    /// Replaces the stored session
+    fn save(&self, key: SessionKey, values: &Map<String, Value>) -> Result<(), Error>;
+
+    /// Deletes the stored session
+    fn remove(&self, key: SessionKey) -> Result<(), Error>;
+}
+
+/// Stores every session as a JSON file `chat_<id>.json` or `user_<id>.json` in a directory
+#[derive(Clone, Debug)]
+pub struct FileSessionStore {
+    dir: PathBuf,
+}
+
+impl FileSessionStore {
+    /// Creates a store in `dir`, which is created with the first saved session
+    pub fn new<P: AsRef<Path>>(dir: P) -> FileSessionStore {
+        FileSessionStore { dir: dir.as_ref().to_path_buf() }
+    }
+
+    fn path(&self, key: SessionKey) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+}
+
+impl SessionStore for FileSessionStore {
+    fn load(&self, key: SessionKey) -> Result<Option<Map<String, Value>>, Error> {
+        let content = match fs::read(self.path(key)) {
+            Ok(content) => content,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(Error::from(err.context(ErrorKind::SessionStore))),
+        };
+
+        let values = serde_json::from_slice(&content).context(ErrorKind::SessionStore)?;
+
+        Ok(Some(values))
+    }
+
+    fn save(&self, key: SessionKey, values: &Map<String, Value>) -> Result<(), Error> {
+        let path = self.path(key);
+        let tmp = path.with_extension(format!("{}.tmp", Uuid::new_v4().to_simple()));
+
+        let content = serde_json::to_vec(values).context(ErrorKind::SessionStore)?;
+
+        // like the offset, the new session is renamed over the old one
+        fs::create_dir_all(&self.dir).context(ErrorKind::SessionStore)?;
+        fs::write(&tmp, content).context(ErrorKind::SessionStore)?;
+        fs::rename(&tmp, &path).context(ErrorKind::SessionStore)?;
+
+        Ok(())
+    }
+
+    fn remove(&self, key: SessionKey) -> Result<(), Error> {
+        match fs::remove_file(self.path(key)) {
+            Ok(()) => Ok(()),
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(Error::from(err.context(ErrorKind::SessionStore))),
+        }
+    }
+}
+
+/// Keeps all sessions in memory, e.g. for tests. All clones share the same sessions.
+#[derive(Clone, Debug, Default)]
+pub struct MemorySessionStore {
+    sessions: Arc<Mutex<HashMap<SessionKey, Map<String, Value>>>>,
+}
+
+impl MemorySessionStore {
+    pub fn new() -> MemorySessionStore {
+        MemorySessionStore::default()
+    }
+}
+
+impl SessionStore for MemorySessionStore {
+    fn load(&self, key: SessionKey) -> Result<Option<Map<String, Value>>, Error> {
+        Ok(self.sessions.lock().unwrap().get(&key).cloned())
+    }
+
+    fn save(&self, key: SessionKey, values: &Map<String, Value>) -> Result<(), Error> {
+        self.sessions.lock().unwrap().insert(key, values.clone());
+
+        Ok(())
+    }
+
+    fn remove(&self, key: SessionKey) -> Result<(), Error> {
+        self.sessions.lock().unwrap().remove(&key);
+
+        Ok(())
+    }
+}
+
+/// The number of pending saves of a session and the sequence number of the last written one
+type Pending = (usize, Arc<Mutex<u64>>);
+
+/// A store together with the order in which the sessions were saved
+///
+/// Every save gets a sequence number when the session is dropped, a save is skipped if a later
+/// one of the same session was written already. A session is tracked while saves are pending.
+pub(crate) struct Sessions {
+    store: Arc<dyn SessionStore>,
+    next: AtomicU64,
+    pending: Mutex<HashMap<SessionKey, Pending>>,
+}
+
+impl Sessions {
+    pub(crate) fn new(store: Arc<dyn SessionStore>) -> Sessions {
+        Sessions {
+            store,
+            next: AtomicU64::new(1),
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Reserves the sequence number of a save, which has to be passed to `write`
+    fn sequence(&self, key: SessionKey) -> u64 {
+        let mut pending = self.pending.lock().unwrap();
+        pending.entry(key).or_default().0 += 1;
+
+        self.next.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Writes the values unless a later save of the session was written before
+    fn write(&self, key: SessionKey, sequence: u64, values: &Map<String, Value>) -> Result<(), Error> {
+        let written = self.pending.lock().unwrap().entry(key).or_default().1.clone();
+
+        let result = {
+            let mut written = written.lock().unwrap();
+
+            if *written > sequence {
+                Ok(())
+            } else {
+                *written = sequence;
+
+                match values.is_empty() {
+                    true => self.store.remove(key),
+                    false => self.store.save(key, values),
+                }
+            }
+        };
+
+        let mut pending = self.pending.lock().unwrap();
+
+        if let Some(entry) = pending.get_mut(&key) {
+            entry.0 = entry.0.saturating_sub(1);
+
+            if entry.0 == 0 {
+                pending.remove(&key);
+            }
+        }
+
+        result
+    }
+}
+
+/// The values of a single chat or user
+///
+/// All clones share the same values. Changes are saved when the last clone is dropped or with
+/// `save`.
+#[derive(Clone)]
+pub struct Session {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    key: SessionKey,
+    sessions: Arc<Sessions>,
+    values: Mutex<Map<String, Value>>,
+    changed: Mutex<bool>,
+}
+
+impl Session {
+    /// Loads the session from the store
+    pub fn load<S: SessionStore + 'static>(key: SessionKey, store: S) -> Result<Session, Error> {
+        Session::load_from(key, Arc::new(Sessions::new(Arc::new(store))))
+    }
+
+    pub(crate) fn load_from(key: SessionKey, sessions: Arc<Sessions>) -> Result<Session, Error> {
+        let values = sessions.store.load(key)?.unwrap_or_default();
+
+        Ok(Session {
+            inner: Arc::new(Inner {
+                key,
+                sessions,
+                values: Mutex::new(values),
+                changed: Mutex::new(false),
+            }),
+        })
+    }
+
+    /// The chat or user of the session
+    pub fn key(&self) -> SessionKey {
+        self.inner.key
+    }
+
+    /// Returns a value or `None` if it was never set
+    pub fn get<T: DeserializeOwned>(&self, name: &str) -> Result<Option<T>, Error> {
+        match self.inner.values.lock().unwrap().get(name) {
+            Some(value) => Ok(Some(T::deserialize(value).context(ErrorKind::SessionStore)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Sets a value
+    pub fn set<T: Serialize>(&self, name: &str, value: T) -> Result<(), Error> {
+        let value = serde_json::to_value(value).context(ErrorKind::SessionStore)?;
+
+        self.inner.values.lock().unwrap().insert(name.into(), value);
+        *self.inner.changed.lock().unwrap() = true;
+
+        Ok(())
+    }
+
+    /// Removes a value
+    pub fn remove(&self, name: &str) {
+        if self.inner.values.lock().unwrap().remove(name).is_some() {
+            *self.inner.changed.lock().unwrap() = true;
+        }
+    }
+
+    /// Removes all values
+    pub fn clear(&self) {
+        self.inner.values.lock().unwrap().clear();
+        *self.inner.changed.lock().unwrap() = true;
+    }
+
+    /// Saves the changes now, this blocks the current thread until they are written
+    pub fn save(&self) -> Result<(), Error> {
+        let mut changed = self.inner.changed.lock().unwrap();
+
+        if !*changed {
+            return Ok(());
+        }
+
+        let values = self.inner.values.lock().unwrap();
+        let sequence = self.inner.sessions.sequence(self.inner.key);
+
+        self.inner.sessions.write(self.inner.key, sequence, &values)?;
+        *changed = false;
+
+        Ok(())
+    }
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        if !*self.changed.get_mut().unwrap() {
+            return;
+        }
+
+        let (key, sessions) = (self.key, self.sessions.clone());
+        let values = std::mem::take(self.values.get_mut().unwrap());
+        let sequence = sessions.sequence(key);
+
+        let save = move || {
+            if let Err(err) = sessions.write(key, sequence, &values) {
+                warn!("Could not save the session {}: {}", key, err);
+            }
+        };
+
+        // the update loop must not wait for the store
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => drop(runtime.spawn_blocking(save)),
+            Err(_) => save(),
+        }
+    }
+}
+
+impl fmt::Debug for Session {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Session")
+            .field("key", &self.inner.key)
+            .field("values", &*self.inner.values.lock().unwrap())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(count: u32) -> Map<String, Value> {
+        let mut values = Map::new();
+        values.insert("count".into(), count.into());
+
+        values
+    }
+
+    #[test]
+    fn later_saves_win() {
+        let store = MemorySessionStore::new();
+        let sessions = Sessions::new(Arc::new(store.clone()));
+        let key = SessionKey::Chat(1);
+
+        let (first, second) = (sessions.sequence(key), sessions.sequence(key));
+
+        sessions.write(key, second, &values(2)).unwrap();
+        sessions.write(key, first, &values(1)).unwrap();
+
+        assert_eq!(store.load(key).unwrap(), Some(values(2)));
+        assert!(sessions.pending.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,55 @@
+//! Stops the update loop of a bot from the outside
+//!
+//! After `ShutdownHandle::shutdown` the bot issues no further `getUpdates` request and cancels
+//! the pending one. It confirms the offset of all dispatched updates with Telegram, waits a
+//! limited time for the handlers to process the remaining messages and then returns from
+//! `Bot::run_with`.
+
+use std::sync::Arc;
+
+use futures::Future;
+use tokio::sync::watch;
+
+/// A clonable handle which requests the shutdown of a bot
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    sender: Arc<watch::Sender<bool>>,
+}
+
+impl Default for ShutdownHandle {
+    fn default() -> ShutdownHandle {
+        ShutdownHandle::new()
+    }
+}
+
+impl ShutdownHandle {
+    pub fn new() -> ShutdownHandle {
+        let (sender, _) = watch::channel(false);
+
+        ShutdownHandle { sender: Arc::new(sender) }
+    }
+
+    /// Stops the update loop, this can't be undone
+    pub fn shutdown(&self) {
+        info!("Shutdown requested");
+
+        self.sender.send_replace(true);
+    }
+
+    /// Returns true if the shutdown was requested
+    pub fn is_shutdown(&self) -> bool {
+        *self.sender.borrow()
+    }
+
+    /// Returns a future which resolves once the shutdown is requested
+    pub fn wait(&self) -> impl Future<Output = ()> + Send + 'static {
+        let sender = self.sender.clone();
+        let mut receiver = sender.subscribe();
+
+        async move {
+            // holding the sender keeps the channel open, it is only left after a shutdown
+            let _sender = sender;
+            let _ = receiver.wait_for(|stop| *stop).await;
+        }
+    }
+}
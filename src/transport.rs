@@ -0,0 +1,406 @@
+//! The transport layer which delivers requests to the Telegram server
+//!
+//! `RequestHandle` serializes every function call and passes it to a `Transport`, which returns
+//! the raw answer of the server. The default transport uses a hyper client, the `MockTransport`
+//! answers requests from memory and records them, so handlers can be tested without network
+//! access:
+//!
+//! ```
+//! use telebot::Bot;
+//! use telebot::functions::*;
+//! use telebot::transport::MockTransport;
+//! use futures::executor::block_on;
+//! use serde_json::json;
+//!
+//! let mock = MockTransport::new();
+//! mock.answer("getMe", json!({"id": 1, "first_name": "Bot", "username": "test_bot"}));
+//!
+//! let bot = Bot::new("TOKEN").transport(mock.clone());
+//! let (_, me) = block_on(bot.request.get_me().send()).unwrap();
+//!
+//! assert_eq!(me.username, Some("test_bot".into()));
+//! assert_eq!(mock.requests()[0].function, "getMe");
+//! ```
+
+use crate::error::{ErrorKind, TelegramError};
+use crate::file::File;
+use crate::objects::Integer;
+
+use std::{cmp, io::{self, Read}, pin::Pin, str, time::Duration, collections::{HashMap, VecDeque}, sync::{Arc, Mutex}};
+
+use hyper::{Body, Client, Request, Uri, header::{CONTENT_LENGTH, CONTENT_TYPE}, client::HttpConnector};
+use hyper_tls::HttpsConnector;
+use serde_json::{self, json, value::Value};
+use futures::{future, stream, Future, FutureExt, Stream, StreamExt, TryFutureExt, TryStreamExt};
+use failure::{Error, Fail, ResultExt};
+use tokio::io::AsyncReadExt;
+use uuid::Uuid;
+
+/// A future which resolves to the raw answer of the Telegram server
+pub type TransportFuture = Pin<Box<dyn Future<Output = Result<String, Error>> + Send>>;
+
+/// A stream which yields the content of a downloaded file in chunks
+pub type TransportStream = Pin<Box<dyn Stream<Item = Result<Vec<u8>, Error>> + Send>>;
+
+/// Delivers requests to the Telegram server
+///
+/// Both methods get the complete URL of the function and the function name itself and return
+/// the unparsed JSON answer, including the `ok` field.
+pub trait Transport: Send + Sync {
+    /// Sends a JSON encoded message
+    fn fetch_json(&self, url: String, func: &'static str, msg: String) -> TransportFuture;
+
+    /// Sends the properties of `msg` together with some files as formdata
+    fn fetch_formdata(&self, url: String, func: &'static str, msg: &Value, files: Vec<File>) -> TransportFuture;
+
+    /// Downloads the content of a file with a GET request to its complete URL
+    fn download(&self, url: String) -> TransportStream;
+}
+
+/// The default transport which uses a hyper client with HTTPS support
+pub struct HyperTransport {
+    client: Client<HttpsConnector<HttpConnector>, Body>,
+}
+
+impl HyperTransport {
+    /// Creates a new client, idle connections are kept alive for `keep_alive`
+    pub fn new(keep_alive: Duration) -> HyperTransport {
+        let client = Client::builder()
+                .pool_idle_timeout(keep_alive)
+                .build(HttpsConnector::new());
+
+        HyperTransport { client }
+    }
+
+    /// Builds the HTTP header for a JSON request. The JSON is already converted to a str and is
+    /// appended to the POST header.
+    fn build_json(
+        &self,
+        url: String,
+        msg: String,
+    ) -> Result<Request<Body>, Error> {
+        let url: Result<Uri, _> = url.parse();
+
+        debug!("Send message {}", msg);
+
+        let req = Request::post(url.context(ErrorKind::Uri)?)
+            .header(CONTENT_TYPE, "application/json")
+            .body(msg.into())
+            .context(ErrorKind::Hyper)?;
+
+        Ok(req)
+    }
+
+    /// Builds the HTTP header for a formdata request. Each property and file is a part of the
+    /// body, in-memory files are read on the blocking thread pool and files on disk are streamed.
+    async fn build_formdata(
+        url: String,
+        msg: Value,
+        files: Vec<File>,
+    ) -> Result<Request<Body>, Error> {
+        let url: Result<Uri, _> = url.parse();
+
+        let boundary = Uuid::new_v4().to_simple().to_string();
+        let mut parts = Vec::new();
+
+        let msg = msg.as_object().ok_or(ErrorKind::JsonNotMap)?;
+
+        // add properties
+        for (key, val) in msg.iter() {
+            let val = match val {
+                Value::String(val) => val.clone(),
+                etc => format!("{}", etc),
+            };
+
+            add_part(&mut parts, &boundary, key, None, Part::Data(val.into_bytes()));
+        }
+
+        for file in files {
+            match file {
+                File::Memory { name, mut source } => {
+                    let content = tokio::task::spawn_blocking(move || {
+                        let mut content = Vec::new();
+                        source.read_to_end(&mut content).map(|_| content)
+                    });
+
+                    let content = content.await.context(ErrorKind::Tokio)?.context(ErrorKind::TelegramFileRead)?;
+
+                    add_part(&mut parts, &boundary, &name, Some(&name), Part::Data(content));
+                }
+                File::Disk { path } => {
+                    let name = path.file_name().and_then(|x| x.to_str()).ok_or(ErrorKind::NoFile)?;
+                    let file = tokio::fs::File::open(&path).await.context(ErrorKind::NoFile)?;
+                    let len = file.metadata().await.context(ErrorKind::TelegramFileRead)?.len();
+
+                    add_part(&mut parts, &boundary, name, Some(name), Part::Disk(file, len));
+                },
+                _ => {}
+            }
+        }
+
+        parts.push(Part::Data(format!("--{}--\r\n", boundary).into_bytes()));
+
+        let len = parts.iter().map(Part::len).sum::<u64>();
+        let body = stream::iter(parts).flat_map(Part::into_stream);
+
+        let req = Request::post(url.context(ErrorKind::Uri)?)
+            .header(CONTENT_TYPE, format!("multipart/form-data; boundary={}", boundary))
+            .header(CONTENT_LENGTH, len)
+            .body(Body::wrap_stream(body))
+            .context(ErrorKind::Hyper)?;
+
+        Ok(req)
+    }
+
+    /// Calls the Telegram API with the request and returns the answer as a String
+    fn fetch(client: &Client<HttpsConnector<HttpConnector>, Body>, request: Result<Request<Body>, Error>) -> TransportFuture {
+        let request = match request {
+            Ok(request) => request,
+            Err(err) => return future::err(err).boxed(),
+        };
+
+        client.request(request)
+            .and_then(|res| hyper::body::to_bytes(res.into_body()))
+            .map_err(|e| Error::from(e.context(ErrorKind::Hyper)))
+            .and_then(|response_chunks| async move {
+                let s = str::from_utf8(&response_chunks).context(ErrorKind::UTF8Decode)?;
+
+                Ok(s.to_string())
+            })
+            .boxed()
+    }
+}
+
+/// A chunk of a multipart body, files on disk are read when the body is sent
+enum Part {
+    Data(Vec<u8>),
+    Disk(tokio::fs::File, u64),
+}
+
+impl Part {
+    fn len(&self) -> u64 {
+        match self {
+            Part::Data(data) => data.len() as u64,
+            Part::Disk(_, len) => *len,
+        }
+    }
+
+    fn into_stream(self) -> impl Stream<Item = Result<Vec<u8>, io::Error>> {
+        match self {
+            Part::Data(data) => stream::once(future::ok(data)).left_stream(),
+            Part::Disk(file, len) => stream::try_unfold((file.take(len), len), |(mut file, left)| async move {
+                if left == 0 {
+                    return Ok(None);
+                }
+
+                let mut chunk = vec![0; cmp::min(left, 64 * 1024) as usize];
+                let read = file.read(&mut chunk).await?;
+
+                // the file got shorter since the length was sent in the header
+                if read == 0 {
+                    return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+                }
+
+                chunk.truncate(read);
+
+                Ok(Some((chunk, (file, left - read as u64))))
+            }).right_stream(),
+        }
+    }
+}
+
+/// Escapes quotes and line breaks of a name in the Content-Disposition header like browsers do,
+/// otherwise a name could end the header and add its own ones
+fn escape(name: &str) -> String {
+    name.replace('"', "%22").replace('\r', "%0D").replace('\n', "%0A")
+}
+
+/// Appends a single part with its content to a multipart body
+fn add_part(parts: &mut Vec<Part>, boundary: &str, name: &str, filename: Option<&str>, content: Part) {
+    let mut header = format!("--{}\r\nContent-Disposition: form-data; name=\"{}\"", boundary, escape(name));
+
+    if let Some(filename) = filename {
+        header.push_str(&format!("; filename=\"{}\"\r\nContent-Type: application/octet-stream", escape(filename)));
+    }
+
+    header.push_str("\r\n\r\n");
+
+    parts.push(Part::Data(header.into_bytes()));
+    parts.push(content);
+    parts.push(Part::Data(b"\r\n".to_vec()));
+}
+
+impl Transport for HyperTransport {
+    fn fetch_json(&self, url: String, _func: &'static str, msg: String) -> TransportFuture {
+        HyperTransport::fetch(&self.client, self.build_json(url, msg))
+    }
+
+    fn fetch_formdata(&self, url: String, _func: &'static str, msg: &Value, files: Vec<File>) -> TransportFuture {
+        let client = self.client.clone();
+        let msg = msg.clone();
+
+        async move {
+            let request = HyperTransport::build_formdata(url, msg, files).await?;
+
+            HyperTransport::fetch(&client, Ok(request)).await
+        }.boxed()
+    }
+
+    fn download(&self, url: String) -> TransportStream {
+        let url = match url.parse::<Uri>() {
+            Ok(url) => url,
+            Err(err) => return stream::once(future::err(Error::from(err.context(ErrorKind::Uri)))).boxed(),
+        };
+
+        self.client.get(url)
+            .map_err(|e| Error::from(e.context(ErrorKind::Hyper)))
+            .map_ok(|res| {
+                let status = res.status();
+
+                if !status.is_success() {
+                    let err = TelegramError::with_details(
+                        format!("Download failed: {}", status),
+                        Some(Integer::from(status.as_u16())),
+                        None
+                    );
+
+                    return stream::once(future::err(Error::from(err.context(ErrorKind::Telegram)))).left_stream();
+                }
+
+                res.into_body()
+                    .map_ok(|chunk| chunk.to_vec())
+                    .map_err(|e| Error::from(e.context(ErrorKind::Hyper)))
+                    .right_stream()
+            })
+            .try_flatten_stream()
+            .boxed()
+    }
+}
+
+/// A request recorded by the `MockTransport`
+#[derive(Clone, Debug)]
+pub struct RecordedRequest {
+    /// The name of the Telegram function, e.g. `sendMessage`, or `download` for a file download
+    pub function: String,
+    /// The message of the request, or the URL of a downloaded file
+    pub body: Value,
+    /// The names of all attached files
+    pub files: Vec<String>,
+}
+
+/// An in-memory transport which answers with canned results and records every request
+///
+/// The answers of each function are returned in the order they were added, the last one is
+/// repeated for all further requests. Functions without an answer fail with a Telegram error.
+#[derive(Clone, Default)]
+pub struct MockTransport {
+    answers: Arc<Mutex<HashMap<String, VecDeque<Value>>>>,
+    files: Arc<Mutex<HashMap<String, Vec<u8>>>>,
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+}
+
+impl MockTransport {
+    pub fn new() -> MockTransport {
+        MockTransport::default()
+    }
+
+    /// Adds a successful answer with this `result` to a function
+    pub fn answer(&self, func: &str, result: Value) -> &MockTransport {
+        self.answer_raw(func, json!({ "ok": true, "result": result }))
+    }
+
+    /// Adds a complete answer to a function, e.g. to simulate an error of the Telegram server
+    pub fn answer_raw(&self, func: &str, answer: Value) -> &MockTransport {
+        self.answers.lock().unwrap()
+            .entry(func.into())
+            .or_default()
+            .push_back(answer);
+
+        self
+    }
+
+    /// Adds the content of a file which is downloaded from `file_path`, the path returned by
+    /// getFile
+    pub fn file(&self, file_path: &str, content: Vec<u8>) -> &MockTransport {
+        self.files.lock().unwrap().insert(file_path.into(), content);
+
+        self
+    }
+
+    /// Returns all requests recorded so far
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// Returns all recorded requests of a function
+    pub fn requests_to(&self, func: &str) -> Vec<RecordedRequest> {
+        self.requests().into_iter().filter(|x| x.function == func).collect()
+    }
+
+    /// Forgets all recorded requests
+    pub fn clear_requests(&self) {
+        self.requests.lock().unwrap().clear();
+    }
+
+    fn record(&self, func: &'static str, body: Value, files: Vec<String>) -> TransportFuture {
+        debug!("Mock request {}: {}", func, body);
+
+        self.requests.lock().unwrap().push(RecordedRequest {
+            function: func.into(),
+            body,
+            files,
+        });
+
+        let answer = match self.answers.lock().unwrap().get_mut(func) {
+            Some(ref mut answers) if answers.len() > 1 => answers.pop_front(),
+            Some(answers) => answers.front().cloned(),
+            None => None,
+        };
+
+        let answer = answer.unwrap_or_else(|| json!({
+            "ok": false,
+            "error_code": 404,
+            "description": format!("Not Found: no answer for {}", func)
+        }));
+
+        future::ok(answer.to_string()).boxed()
+    }
+}
+
+impl Transport for MockTransport {
+    fn fetch_json(&self, _url: String, func: &'static str, msg: String) -> TransportFuture {
+        match serde_json::from_str(&msg) {
+            Ok(body) => self.record(func, body, Vec::new()),
+            Err(err) => future::err(Error::from(err.context(ErrorKind::JsonParse))).boxed(),
+        }
+    }
+
+    fn fetch_formdata(&self, _url: String, func: &'static str, msg: &Value, files: Vec<File>) -> TransportFuture {
+        let files = files.iter().map(File::name).collect();
+
+        self.record(func, msg.clone(), files)
+    }
+
+    fn download(&self, url: String) -> TransportStream {
+        debug!("Mock download {}", url);
+
+        self.requests.lock().unwrap().push(RecordedRequest {
+            function: "download".into(),
+            body: Value::String(url.clone()),
+            files: Vec::new(),
+        });
+
+        let content = self.files.lock().unwrap().iter()
+            .find(|(path, _)| url.ends_with(&format!("/{}", path)))
+            .map(|(_, content)| content.clone());
+
+        match content {
+            Some(content) => stream::once(future::ok(content)).boxed(),
+            None => {
+                let err = TelegramError::with_details("Not Found: no such file".into(), Some(404), None);
+
+                stream::once(future::err(Error::from(err.context(ErrorKind::Telegram)))).boxed()
+            }
+        }
+    }
+}
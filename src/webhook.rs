@@ -0,0 +1,161 @@
+//! A local HTTP listener which receives updates pushed by Telegram
+//!
+//! Telegram POSTs every update as a JSON encoded `Update` object to the URL registered with
+//! setWebhook. The listener checks the secret path and token of each request and forwards the
+//! update to the same dispatch logic which is used for long polling. An update is only answered
+//! once the stream took it, so that Telegram keeps further updates while the bot is busy.
+
+use crate::objects;
+use crate::error::ErrorKind;
+
+use std::{convert::Infallible, net::SocketAddr};
+
+use hyper::{Body, Method, Request, Response, Server, StatusCode, service::{make_service_fn, service_fn}};
+use futures::{future, stream, FutureExt, SinkExt, Stream, StreamExt, TryStreamExt, channel::mpsc::{self, Sender}};
+use failure::{Error, Fail, ResultExt};
+
+/// The header which carries the secret token configured with setWebhook
+pub const SECRET_TOKEN_HEADER: &str = "X-Telegram-Bot-Api-Secret-Token";
+
+/// Configuration of the local webhook listener
+///
+/// The listener accepts POST requests on `path` and, if a secret token is set, only those which
+/// carry it in the `X-Telegram-Bot-Api-Secret-Token` header.
+#[derive(Clone, Debug)]
+pub struct Webhook {
+    addr: SocketAddr,
+    path: String,
+    secret_token: Option<String>,
+}
+
+impl Webhook {
+    /// Creates a new listener configuration bound to `addr` which accepts updates on `path`
+    pub fn new(addr: SocketAddr, path: &str) -> Webhook {
+        let path = if path.starts_with('/') {
+            path.into()
+        } else {
+            format!("/{}", path)
+        };
+
+        Webhook {
+            addr,
+            path,
+            secret_token: None,
+        }
+    }
+
+    /// Only accept requests which carry this secret token
+    pub fn secret_token(mut self, token: &str) -> Webhook {
+        self.secret_token = Some(token.into());
+
+        self
+    }
+
+    /// The local address of the listener
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// The secret path on which updates are accepted
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Checks the path and the secret token of an incoming request
+    fn verify(&self, req: &Request<Body>) -> Result<(), StatusCode> {
+        if req.uri().path() != self.path {
+            return Err(StatusCode::NOT_FOUND);
+        }
+
+        if req.method() != Method::POST {
+            return Err(StatusCode::METHOD_NOT_ALLOWED);
+        }
+
+        if let Some(ref token) = self.secret_token {
+            let given = req.headers().get(SECRET_TOKEN_HEADER).and_then(|x| x.to_str().ok());
+
+            if given != Some(token.as_str()) {
+                return Err(StatusCode::UNAUTHORIZED);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Binds the listener and returns a stream of all received updates
+    ///
+    /// The stream runs the HTTP server while it is polled. Requests with a wrong path, method or
+    /// token are rejected and never reach the stream. Every connection holds at most one update
+    /// which the stream didn't take yet.
+    pub fn listen(self) -> impl Stream<Item = Result<objects::Update, Error>> {
+        let (sender, receiver) = mpsc::channel(0);
+        let addr = self.addr;
+
+        let new_service = make_service_fn(move |_| {
+            let config = self.clone();
+            let sender = sender.clone();
+
+            future::ok::<_, Infallible>(service_fn(move |req| {
+                receive(config.clone(), sender.clone(), req)
+            }))
+        });
+
+        let server = async move {
+            let builder = Server::try_bind(&addr).context(ErrorKind::Webhook)?;
+
+            info!("Listening for webhook updates on {}", addr);
+
+            builder
+                .serve(new_service)
+                .await
+                .map_err(|e| Error::from(e.context(ErrorKind::Hyper)))
+        };
+
+        // the server never yields an update itself, but has to be polled together with the
+        // receiver to accept connections
+        stream::select(
+            receiver.map(Ok),
+            server.into_stream().try_filter_map(|_| future::ok(None))
+        )
+    }
+}
+
+/// Handles a single request of the Telegram server
+async fn receive(
+    config: Webhook,
+    mut sender: Sender<objects::Update>,
+    req: Request<Body>
+) -> Result<Response<Body>, hyper::Error> {
+    if let Err(status) = config.verify(&req) {
+        warn!("Rejected webhook request to {} with {}", req.uri().path(), status);
+
+        return Ok(respond(status));
+    }
+
+    let body = hyper::body::to_bytes(req.into_body()).await?;
+
+    let res = match serde_json::from_slice::<objects::Update>(&body) {
+        Ok(update) => {
+            debug!("Got an update from the webhook: {:?}", update);
+
+            match sender.send(update).await {
+                Ok(_) => respond(StatusCode::OK),
+                Err(_) => respond(StatusCode::SERVICE_UNAVAILABLE),
+            }
+        }
+        Err(err) => {
+            warn!("Could not parse the update from the webhook: {}", err);
+
+            respond(StatusCode::BAD_REQUEST)
+        }
+    };
+
+    Ok(res)
+}
+
+fn respond(status: StatusCode) -> Response<Body> {
+    let mut res = Response::new(Body::empty());
+    *res.status_mut() = status;
+
+    res
+}
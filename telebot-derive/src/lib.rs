@@ -260,48 +260,31 @@ fn expand_function(ast: syn::MacroInput) -> quote::Tokens {
                 }
             }
             impl #wrapper_name {
-                pub fn send<'a>(self) -> impl Future<Item=(RequestHandle, objects::#answer), Error=Error> + 'a{
-                    use futures::future::result;
-                    use futures::IntoFuture;
-
-                    let cloned_bot = self.bot.clone();
-
-                    result::<#wrapper_name, Error>(Ok(self))
-                        .and_then(move |mut tmp| {
-                            let #wrapper_name { bot, mut inner, mut file } = tmp;
-
-                            match file {
-                                Ok(files) => {
-                                    inner.#file_kind = files.to_metadata();
-
-                                    match serde_json::to_value(&inner) {
-                                        Ok(msg) => {
-                                            Ok((bot, msg, files.into_files()))
-                                        },
-                                        Err(err) => Err(Error::from(err.context(ErrorKind::JsonSerialize)))
-                                     }
-                                },
-                                Err(err) => Err(err)
-                            }
-                        })
-                        .and_then(move |(bot, msg, files)| {
-                            let bot = bot.clone();
-                            let bot2 = bot.clone();
-                            let msg_str = serde_json::to_string(&msg).unwrap();
-
-                            files.ok_or(Error::from(ErrorKind::NoFile)).into_future()
-                                .and_then(move |files| {
-                                    bot.fetch_formdata(#function, &msg, files, #file_kind_name)
-                                })
-                                .or_else(move |_| {
-                                    bot2.fetch_json(#function, &msg_str)
-                                })
-                        })
-                        .and_then(move |answer| {
-                            serde_json::from_str::<objects::#answer>(&answer)
-                                .map(|json| (cloned_bot, json))
-                                .map_err(|x| Error::from(x.context(ErrorKind::JsonParse)))
-                        })
+                /// Sends the request and blocks the current thread until the answer arrives
+                pub fn send_blocking(self) -> Result<objects::#answer, Error> {
+                    crate::blocking::block_on(self.send()).map(|(_, answer)| answer)
+                }
+
+                pub async fn send(self) -> Result<(RequestHandle, objects::#answer), Error> {
+                    let #wrapper_name { bot, mut inner, file } = self;
+
+                    let files = file?;
+                    inner.#file_kind = files.to_metadata(#file_kind_name);
+
+                    let mut msg = serde_json::to_value(&inner)
+                        .map_err(|e| Error::from(e.context(ErrorKind::JsonSerialize)))?;
+
+                    files.apply_properties(#file_kind_name, &mut msg)?;
+
+                    let answer = match files.into_files() {
+                        Some(files) => bot.fetch_formdata(#function, &msg, files, #file_kind_name).await?,
+                        None => bot.fetch_json(#function, &msg.to_string()).await?
+                    };
+
+                    let json = serde_json::from_str::<objects::#answer>(&answer)
+                        .map_err(|e| Error::from(e.context(ErrorKind::JsonParse)))?;
+
+                    Ok((bot, json))
                 }
 
                 #(
@@ -312,28 +295,17 @@ fn expand_function(ast: syn::MacroInput) -> quote::Tokens {
                     }
                 )*
 
-                pub fn file<S, E>(mut self, val: S) -> Self where S: file::TryIntoFile<Error = E> {
-                    let val: Result<file::File, E> = val.try_into();
-
-                    let new_val = match self.file {
-                        Ok(mut filelist) => {
-                            match val {
-                                Ok(val) => {
-                                    filelist.push(file::FileWithCaption::new_empty(val));
-                                    Ok(filelist)
-                                },
-                                Err(err) => {
-                                    Err(Error::from(ErrorKind::NoFile))
-                                }
-                            }
+                /// Adds a file, the first conversion error is returned by `send`
+                pub fn file<S>(mut self, val: S) -> Self where S: file::TryIntoMedia, S::Error: Into<Error> {
+                    self.file = match (self.file, val.try_into_media()) {
+                        (Ok(mut filelist), Ok(val)) => {
+                            filelist.push(val);
+                            Ok(filelist)
                         },
-                        Err(_) => {
-                            Err(Error::from(ErrorKind::NoFile))
-                        }
+                        (Ok(_), Err(err)) => Err(err.into()),
+                        (Err(err), _) => Err(err),
                     };
 
-                    self.file = new_val;
-
                     self
                 }
             }
@@ -352,20 +324,21 @@ fn expand_function(ast: syn::MacroInput) -> quote::Tokens {
                 }
             }
             impl #wrapper_name {
-                pub fn send<'a>(self) -> impl Future<Item=(RequestHandle, objects::#answer), Error=Error> + 'a{
-                    use futures::future::result;
-                    result(serde_json::to_string(&self.inner))
-                        .map_err(|e| Error::from(e.context(ErrorKind::JsonSerialize)))
-                        .and_then(move |msg| {
-                            let obj = self.bot.fetch_json(#function, &msg)
-                                .and_then(move |x| {
-                                    serde_json::from_str::<objects::#answer>(&x)
-                                        .map(|json| (self.bot.clone(), json))
-                                        .map_err(|x| Error::from(x.context(ErrorKind::JsonParse)))
-                                });
-
-                            Box::new(obj)
-                        })
+                /// Sends the request and blocks the current thread until the answer arrives
+                pub fn send_blocking(self) -> Result<objects::#answer, Error> {
+                    crate::blocking::block_on(self.send()).map(|(_, answer)| answer)
+                }
+
+                pub async fn send(self) -> Result<(RequestHandle, objects::#answer), Error> {
+                    let msg = serde_json::to_string(&self.inner)
+                        .map_err(|e| Error::from(e.context(ErrorKind::JsonSerialize)))?;
+
+                    let answer = self.bot.fetch_json(#function, &msg).await?;
+
+                    let json = serde_json::from_str::<objects::#answer>(&answer)
+                        .map_err(|e| Error::from(e.context(ErrorKind::JsonParse)))?;
+
+                    Ok((self.bot, json))
                 }
 
                 #(
@@ -380,6 +353,149 @@ fn expand_function(ast: syn::MacroInput) -> quote::Tokens {
     }
 }
 
+#[proc_macro_derive(BotCommands, attributes(command, description, scope, hidden))]
+pub fn derive_bot_commands(input: TokenStream) -> TokenStream {
+    let ast = syn::parse_macro_input(&input.to_string()).unwrap();
+    let expanded = expand_bot_commands(ast);
+    expanded.to_string().parse().unwrap()
+}
+
+fn expand_bot_commands(ast: syn::MacroInput) -> quote::Tokens {
+    let name = &ast.ident;
+
+    let variants = match ast.body {
+        syn::Body::Enum(ref variants) => variants,
+        _ => panic!("#[derive(BotCommands)] can only be used with enums"),
+    };
+
+    let mut commands = Vec::new();
+    let mut infos = Vec::new();
+    let mut arms = Vec::new();
+
+    for variant in variants {
+        let config = config_from(&variant.attrs);
+        let ident = &variant.ident;
+
+        let command = match config.get("command") {
+            Some(command) => format!("/{}", command.trim_start_matches('/')),
+            None => format!("/{}", snake_case(ident.as_ref())),
+        };
+
+        // every field is bound to a variable and parsed in order, tuple fields are labeled with
+        // their position
+        let (vars, labels, tys, construct) = match variant.data {
+            syn::VariantData::Unit => (vec![], vec![], vec![], quote! { #name::#ident }),
+            syn::VariantData::Tuple(ref fields) => {
+                let vars: Vec<_> = (1..fields.len() + 1)
+                    .map(|i| syn::Ident::from(format!("arg{}", i)))
+                    .collect();
+                let labels = vars.iter().map(|x| syn::Lit::from(x.as_ref())).collect();
+                let tys = fields.iter().map(|f| &f.ty).collect();
+                let vars2 = vars.clone();
+
+                (vars, labels, tys, quote! { #name::#ident(#(#vars2),*) })
+            }
+            syn::VariantData::Struct(ref fields) => {
+                let vars: Vec<_> = fields.iter().map(|f| f.ident.clone().unwrap()).collect();
+                let labels = vars.iter().map(|x| syn::Lit::from(x.as_ref())).collect();
+                let tys = fields.iter().map(|f| &f.ty).collect();
+                let vars2 = vars.clone();
+
+                (vars, labels, tys, quote! { #name::#ident { #(#vars2),* } })
+            }
+        };
+
+        let description = syn::Lit::from(config.get("description").map(|x| x.as_str()).unwrap_or(""));
+
+        let scope = match config.get("scope").map(|x| x.as_str()) {
+            None | Some("all") => syn::Ident::from("All"),
+            Some("private") => syn::Ident::from("Private"),
+            Some("groups") => syn::Ident::from("Groups"),
+            Some("admins") => syn::Ident::from("Admins"),
+            Some(other) => panic!("unknown command scope {}, expected all, private, groups or admins", other),
+        };
+
+        let hidden = variant.attrs.iter().any(|attr| match attr.value {
+            syn::MetaItem::Word(ref name) => name.as_ref() == "hidden",
+            _ => false,
+        });
+
+        let command = syn::Lit::from(command.as_str());
+        let command2 = command.clone();
+
+        infos.push(quote! {
+            ::telebot::command::CommandInfo::new(#command, #description)
+                .scope(::telebot::command::CommandScope::#scope)
+                .hidden(#hidden)
+        });
+        let labels2 = labels.clone();
+        let tys2 = tys.clone();
+
+        arms.push(quote! {
+            #command => {
+                fn parse_args(args: &str) -> ::std::result::Result<#name, ::telebot::command::CommandError> {
+                    // the parser must not be shadowed by a field with the same name
+                    #[allow(unused_mut)]
+                    let mut __telebot_args = ::telebot::command::ArgParser::new(args);
+
+                    #(let #vars = __telebot_args.arg::<#tys>(#labels)?;)*
+
+                    __telebot_args.finish()?;
+
+                    Ok(#construct)
+                }
+
+                parse_args(args).map_err(|err| {
+                    let usage: Vec<String> = vec![
+                        #command2.to_string()
+                        #(, <#tys2 as ::telebot::command::CommandArg>::usage(#labels2))*
+                    ];
+
+                    err.with_usage(usage.join(" "))
+                })
+            }
+        });
+
+        commands.push(command);
+    }
+
+    quote! {
+        impl ::telebot::command::BotCommands for #name {
+            fn names() -> Vec<&'static str> {
+                vec![#(#commands),*]
+            }
+
+            fn descriptions() -> Vec<::telebot::command::CommandInfo> {
+                vec![#(#infos),*]
+            }
+
+            fn parse(name: &str, args: &str) -> ::std::result::Result<#name, ::telebot::command::CommandError> {
+                match name {
+                    #(#arms)*
+                    _ => Err(::telebot::command::CommandError::new(
+                        ::telebot::command::CommandErrorKind::UnknownCommand(name.to_string())
+                    )),
+                }
+            }
+        }
+    }
+}
+
+/// Converts a variant name like `SetTimer` to `set_timer`
+fn snake_case(name: &str) -> String {
+    let mut result = String::new();
+
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            result.push('_');
+        }
+
+        result.extend(c.to_lowercase());
+    }
+
+    result
+}
+
 fn config_from(attrs: &[syn::Attribute]) -> BTreeMap<String, String> {
     let mut result = BTreeMap::new();
     for attr in attrs {
@@ -0,0 +1,56 @@
+use telebot::command::{BotCommands, CommandErrorKind, Rest};
+
+#[derive(BotCommands, Debug, PartialEq)]
+enum Command {
+    Start,
+    Run { args: String, times: u32 },
+    Say(Rest),
+}
+
+#[test]
+fn fields_may_be_named_like_the_parser() {
+    assert_eq!(
+        Command::from_text("/run build 3", None).unwrap(),
+        Command::Run { args: "build".into(), times: 3 }
+    );
+}
+
+#[test]
+fn missing_arguments_are_reported_with_the_usage() {
+    let err = Command::from_text("/run build", None).unwrap_err();
+
+    assert_eq!(err.kind, CommandErrorKind::MissingArgument("times".into()));
+    assert_eq!(err.usage.as_deref(), Some("/run <args> <times>"));
+}
+
+#[test]
+fn extra_arguments_are_rejected() {
+    let err = Command::from_text("/start now", None).unwrap_err();
+    assert!(matches!(err.kind, CommandErrorKind::TooManyArguments(_)));
+
+    let err = Command::from_text("/run build 3 4", None).unwrap_err();
+    assert!(matches!(err.kind, CommandErrorKind::TooManyArguments(_)));
+
+    // the rest of the line takes every argument
+    assert_eq!(Command::from_text("/say a b c", None).unwrap(), Command::Say(Rest("a b c".into())));
+}
+
+#[test]
+fn badly_typed_arguments_are_rejected() {
+    let err = Command::from_text("/run build often", None).unwrap_err();
+
+    match err.kind {
+        CommandErrorKind::InvalidArgument { name, value, .. } => {
+            assert_eq!(name, "times");
+            assert_eq!(value, "often");
+        }
+        other => panic!("unexpected error {:?}", other),
+    }
+}
+
+#[test]
+fn unknown_commands_are_rejected() {
+    let err = Command::from_text("/stop", None).unwrap_err();
+
+    assert_eq!(err.kind, CommandErrorKind::UnknownCommand("/stop".into()));
+}
@@ -0,0 +1,93 @@
+//! Fixtures shared by the integration tests
+
+#![allow(dead_code)]
+
+use telebot::bot::RequestHandle;
+use telebot::error::ErrorKind;
+use telebot::objects::Update;
+use telebot::transport::{MockTransport, RecordedRequest};
+
+use std::time::Duration;
+
+use failure::Context;
+use futures::{FutureExt, Stream, StreamExt};
+use serde_json::{json, Value};
+
+/// The answer of a function which sent a message to chat 7
+pub fn message() -> Value {
+    json!({"message_id": 1, "date": 0, "chat": {"id": 7, "type": "private"}})
+}
+
+/// A failed answer of the Telegram server
+pub fn error(code: i64, parameters: Value) -> Value {
+    json!({"ok": false, "error_code": code, "description": "Error", "parameters": parameters})
+}
+
+/// Returns the kind of an error returned by the library
+pub fn kind(err: failure::Error) -> ErrorKind {
+    err.iter_chain()
+        .find_map(|x| {
+            x.downcast_ref::<ErrorKind>().copied()
+                .or_else(|| x.downcast_ref::<Context<ErrorKind>>().map(|x| *x.get_context()))
+                .or_else(|| x.downcast_ref::<telebot::error::Error>().map(|x| x.kind()))
+        })
+        .unwrap()
+}
+
+/// A text message of `user` in `chat`, texts which start with a slash are sent as commands
+pub fn text(update_id: i64, chat: i64, user: i64, text: &str) -> Update {
+    serde_json::from_value(text_update(update_id, chat, user, text)).unwrap()
+}
+
+/// The JSON of the update returned by `text`
+pub fn text_update(update_id: i64, chat: i64, user: i64, text: &str) -> Value {
+    let kind = if chat > 0 { "private" } else { "group" };
+    let mut message = json!({
+        "message_id": update_id,
+        "date": 0,
+        "chat": {"id": chat, "type": kind},
+        "from": {"id": user, "first_name": "Test"},
+        "text": text
+    });
+
+    if text.starts_with('/') {
+        let length = text.find(' ').unwrap_or(text.len());
+        message["entities"] = json!([{"type": "bot_command", "offset": 0, "length": length}]);
+    }
+
+    json!({"update_id": update_id, "message": message})
+}
+
+/// A callback query with `data` of a button in `chat`
+pub fn callback(update_id: i64, chat: i64, user: i64, data: &str) -> Update {
+    serde_json::from_value(json!({
+        "update_id": update_id,
+        "callback_query": {
+            "id": update_id.to_string(),
+            "from": {"id": user, "first_name": "Test"},
+            "chat_instance": "1",
+            "data": data,
+            "message": {"message_id": 1, "date": 0, "chat": {"id": chat, "type": "private"}}
+        }
+    })).unwrap()
+}
+
+/// Waits until the mock received `count` requests to `function` and returns them
+pub async fn wait_for(mock: &MockTransport, function: &str, count: usize) -> Vec<RecordedRequest> {
+    for _ in 0..500 {
+        let requests = mock.requests_to(function);
+
+        if requests.len() >= count {
+            return requests;
+        }
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    panic!("{} was not called {} times", function, count);
+}
+
+/// Returns the item which was already sent to the stream
+pub fn received<T>(stream: &mut (impl Stream<Item = Result<(RequestHandle, T), failure::Error>> + Unpin)) -> Option<T> {
+    stream.next().now_or_never().flatten().map(|x| x.unwrap().1)
+}
@@ -0,0 +1,147 @@
+mod common;
+
+use telebot::Bot;
+use telebot::bot::RequestHandle;
+use telebot::dialogue::{Dialogue, DialogueInput, DialogueKey, DialogueUpdate};
+use telebot::guard::Guard;
+use telebot::transport::MockTransport;
+
+use std::time::Duration;
+
+use failure::Error;
+use futures::{stream::BoxStream, StreamExt};
+use serde_json::json;
+
+use common::{callback, received, text};
+
+#[test]
+fn clones_can_be_configured() {
+    let dialogue = Dialogue::new().entry("/start", 1);
+    let other = dialogue.clone().timeout(Duration::from_secs(60)).entry("/again", 2);
+
+    // the conversations are still shared
+    other.start(DialogueKey(7), 7, 1);
+    assert_eq!(dialogue.get(DialogueKey(7)), Some(1));
+
+    dialogue.set(DialogueKey(7), 2);
+    assert_eq!(other.exit(DialogueKey(7)), Some(2));
+}
+
+type Updates = BoxStream<'static, Result<(RequestHandle, DialogueUpdate<u32>), Error>>;
+
+/// Starts a conversation with /signup and ends it with /stop
+fn signup(bot: &mut Bot) -> (Dialogue<u32>, Updates) {
+    let dialogue = Dialogue::new().entry("/signup", 1).cancel_cmds(&["/stop"]);
+    let updates = bot.dialogue(dialogue.clone()).boxed();
+
+    (dialogue, updates)
+}
+
+#[tokio::test]
+async fn entry_commands_start_a_conversation() {
+    let mut bot = Bot::new("TOKEN");
+    let (dialogue, mut updates) = signup(&mut bot);
+
+    assert!(bot.dispatch(text(1, 7, 7, "/signup")).await.is_none());
+    let update = received(&mut updates).unwrap();
+    assert_eq!((update.key, update.chat_id, update.state), (DialogueKey(7), 7, 1));
+    assert!(matches!(update.input, DialogueInput::Start(_)));
+
+    dialogue.set(DialogueKey(7), 2);
+
+    assert!(bot.dispatch(text(2, 7, 7, "Alice")).await.is_none());
+    let update = received(&mut updates).unwrap();
+    assert_eq!(update.state, 2);
+    assert!(matches!(update.input, DialogueInput::Message(ref msg) if msg.text.as_deref() == Some("Alice")));
+}
+
+#[tokio::test]
+async fn cancel_commands_end_the_conversation() {
+    let mut bot = Bot::new("TOKEN");
+    let (dialogue, mut updates) = signup(&mut bot);
+
+    bot.dispatch(text(1, 7, 7, "/signup")).await;
+    received(&mut updates).unwrap();
+
+    assert!(bot.dispatch(text(2, 7, 7, "/stop")).await.is_none());
+    assert!(matches!(received(&mut updates).unwrap().input, DialogueInput::Cancelled));
+    assert_eq!(dialogue.get(DialogueKey(7)), None);
+
+    // outside of a conversation the message is not taken
+    assert!(bot.dispatch(text(3, 7, 7, "Alice")).await.is_some());
+    assert!(received(&mut updates).is_none());
+}
+
+#[tokio::test]
+async fn silent_conversations_time_out() {
+    let mut bot = Bot::new("TOKEN");
+    let dialogue = Dialogue::new().entry("/signup", 1).timeout(Duration::from_millis(20));
+    let mut updates = bot.dialogue(dialogue.clone()).boxed();
+
+    bot.dispatch(text(1, 7, 7, "/signup")).await;
+    received(&mut updates).unwrap();
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    // the late answer ends the conversation and is handled as usual
+    assert!(bot.dispatch(text(2, 7, 7, "Alice")).await.is_some());
+    assert!(matches!(received(&mut updates).unwrap().input, DialogueInput::TimedOut));
+    assert_eq!(dialogue.get(DialogueKey(7)), None);
+}
+
+#[tokio::test]
+async fn callback_queries_of_a_conversation_are_routed_to_it() {
+    let mut bot = Bot::new("TOKEN");
+    let (_, mut updates) = signup(&mut bot);
+    let mut callbacks = bot.callback().boxed();
+
+    bot.dispatch(text(1, 7, 7, "/signup")).await;
+    received(&mut updates).unwrap();
+
+    bot.dispatch(callback(2, 7, 7, "yes")).await;
+    assert!(matches!(received(&mut updates).unwrap().input, DialogueInput::Callback(ref query) if query.data.as_deref() == Some("yes")));
+
+    // buttons in other chats reach the callback stream
+    bot.dispatch(callback(3, 8, 8, "no")).await;
+    assert_eq!(received(&mut callbacks).unwrap().data.unwrap(), "no");
+    assert!(received(&mut updates).is_none());
+}
+
+#[tokio::test]
+async fn every_chat_has_its_own_conversation() {
+    let mut bot = Bot::new("TOKEN");
+    let (dialogue, mut updates) = signup(&mut bot);
+
+    bot.dispatch(text(1, 7, 7, "/signup")).await;
+    received(&mut updates).unwrap();
+    dialogue.set(DialogueKey(7), 2);
+
+    bot.dispatch(text(2, 8, 8, "/signup")).await;
+    assert_eq!(received(&mut updates).unwrap().state, 1);
+
+    bot.dispatch(text(3, 8, 8, "/stop")).await;
+    received(&mut updates).unwrap();
+
+    assert_eq!(dialogue.get(DialogueKey(7)), Some(2));
+    assert_eq!(dialogue.get(DialogueKey(8)), None);
+}
+
+#[tokio::test]
+async fn guarded_entry_commands_need_the_permission() {
+    let mock = MockTransport::new();
+    mock.answer("getChatAdministrators", json!([
+        {"user": {"id": 1, "first_name": "Admin"}, "status": "administrator"}
+    ]));
+
+    let mut bot = Bot::new("TOKEN").transport(mock.clone());
+    let (dialogue, mut updates) = signup(&mut bot);
+    bot.guard("/signup", Guard::admins().silent());
+
+    assert!(bot.dispatch(text(1, -5, 2, "/signup")).await.is_none());
+    assert!(bot.dispatch(text(2, -5, 1, "/signup")).await.is_none());
+
+    let (_, update) = tokio::time::timeout(Duration::from_secs(5), updates.next()).await.unwrap().unwrap().unwrap();
+    assert!(matches!(update.input, DialogueInput::Start(ref msg) if msg.from.as_ref().unwrap().id == 1));
+    assert_eq!(dialogue.get(DialogueKey(-5)), Some(1));
+    assert!(received(&mut updates).is_none());
+}
@@ -0,0 +1,52 @@
+mod common;
+
+use telebot::Bot;
+use telebot::error::{ErrorKind, TelegramError};
+use telebot::transport::MockTransport;
+
+use serde_json::json;
+
+use common::kind;
+
+fn mock_bot(size: i64, path: &str) -> (Bot, MockTransport) {
+    let mock = MockTransport::new();
+    mock.answer("getFile", json!({"file_id": "FILE", "file_size": size, "file_path": path}));
+    mock.file("photos/bee.jpg", b"hello".to_vec());
+
+    (Bot::new("TOKEN").transport(mock.clone()), mock)
+}
+
+#[tokio::test]
+async fn downloads_the_content() {
+    let (bot, mock) = mock_bot(5, "photos/bee.jpg");
+
+    let (file, content) = bot.request.download_file_to("FILE".into(), Vec::new()).await.unwrap();
+
+    assert_eq!(content, b"hello");
+    assert_eq!(file.file_path, Some("photos/bee.jpg".into()));
+    assert!(mock.requests_to("download")[0].body.as_str().unwrap().ends_with("/file/botTOKEN/photos/bee.jpg"));
+}
+
+#[tokio::test]
+async fn rejects_content_of_another_size() {
+    for size in [3, 10] {
+        let (bot, _) = mock_bot(size, "photos/bee.jpg");
+
+        let err = bot.request.download_file_to("FILE".into(), Vec::new()).await.err().unwrap();
+        assert_eq!(kind(err), ErrorKind::FileSize);
+    }
+}
+
+#[tokio::test]
+async fn fails_for_missing_files() {
+    let (bot, _) = mock_bot(5, "photos/missing.jpg");
+    let err = bot.request.download_file_to("FILE".into(), Vec::new()).await.err().unwrap();
+    assert_eq!(TelegramError::find(&err).and_then(|x| x.error_code()), Some(404));
+
+    // a Bot API server in local mode returns absolute paths
+    let path = std::env::temp_dir().join("telebot-missing-download");
+    let (bot, mock) = mock_bot(5, path.to_str().unwrap());
+    let err = bot.request.download_file_to("FILE".into(), Vec::new()).await.err().unwrap();
+    assert_eq!(kind(err), ErrorKind::IO);
+    assert!(mock.requests_to("download").is_empty());
+}
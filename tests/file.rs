@@ -0,0 +1,83 @@
+mod common;
+
+use telebot::Bot;
+use telebot::error::ErrorKind;
+use telebot::file::InputMedia;
+use telebot::functions::*;
+use telebot::transport::MockTransport;
+
+use std::io::Cursor;
+
+use common::{kind, message};
+
+#[tokio::test]
+async fn single_media_sets_the_function_properties() {
+    let mock = MockTransport::new();
+    mock.answer("sendVideo", message());
+
+    let video = InputMedia::video(("bee.mp4", Cursor::new(vec![0u8; 4])))
+        .caption("A bee")
+        .thumb(("thumb.jpg", Cursor::new(vec![0u8; 4])))
+        .width(640);
+
+    let bot = Bot::new("TOKEN").transport(mock.clone());
+    bot.request.video(7).file(video).send().await.unwrap();
+
+    let request = mock.requests_to("sendVideo").remove(0);
+    assert_eq!(request.body["video"], "attach://bee.mp4");
+    assert_eq!(request.body["caption"], "A bee");
+    assert_eq!(request.body["thumb"], "attach://thumb.jpg");
+    assert_eq!(request.body["width"], 640);
+    assert_eq!(request.files, vec!["attach://bee.mp4", "attach://thumb.jpg"]);
+}
+
+#[tokio::test]
+async fn function_properties_win_over_the_media() {
+    let mock = MockTransport::new();
+    mock.answer("sendPhoto", message());
+
+    let photo = InputMedia::photo(("bee.jpg", Cursor::new(vec![0u8; 4]))).caption("media");
+
+    let bot = Bot::new("TOKEN").transport(mock.clone());
+    bot.request.photo(7).caption("function").file(photo).send().await.unwrap();
+
+    assert_eq!(mock.requests_to("sendPhoto")[0].body["caption"], "function");
+}
+
+#[tokio::test]
+async fn single_media_of_another_type_is_rejected() {
+    let mock = MockTransport::new();
+    let audio = InputMedia::audio(("song.mp3", Cursor::new(vec![0u8; 4])));
+
+    let bot = Bot::new("TOKEN").transport(mock.clone());
+    let err = bot.request.video(7).file(audio).send().await.err().unwrap();
+
+    assert_eq!(kind(err), ErrorKind::MediaType);
+    assert!(mock.requests().is_empty());
+}
+
+#[tokio::test]
+async fn conversion_errors_are_returned() {
+    let mock = MockTransport::new();
+
+    let bot = Bot::new("TOKEN").transport(mock.clone());
+    let err = bot.request.mediagroup(7)
+        .file(InputMedia::photo("does/not/exist.jpg"))
+        .file(InputMedia::photo(("bee.jpg", Cursor::new(vec![0u8; 4]))))
+        .send()
+        .await
+        .err()
+        .unwrap();
+
+    assert_eq!(kind(err), ErrorKind::NoFile);
+
+    let err = bot.request.video(7)
+        .file(InputMedia::video(("bee.mp4", Cursor::new(vec![0u8; 4]))).thumb("does/not/exist.jpg"))
+        .send()
+        .await
+        .err()
+        .unwrap();
+
+    assert_eq!(kind(err), ErrorKind::NoFile);
+    assert!(mock.requests().is_empty());
+}
@@ -0,0 +1,109 @@
+mod common;
+
+use telebot::Bot;
+use telebot::file::File;
+use telebot::functions::*;
+use telebot::guard::Guard;
+use telebot::transport::{MockTransport, Transport, TransportFuture, TransportStream};
+
+use std::time::Duration;
+
+use futures::{future, FutureExt, StreamExt};
+use serde_json::{json, Value};
+
+use common::{error, text};
+
+#[tokio::test]
+#[allow(deprecated)]
+async fn the_former_name_still_requests_the_administrators() {
+    let mock = MockTransport::new();
+    mock.answer("getChatAdministrators", json!([]));
+
+    let bot = Bot::new("TOKEN").transport(mock.clone());
+    bot.request.unban_chat_administrators(-5).send().await.unwrap();
+
+    assert_eq!(mock.requests_to("getChatAdministrators").len(), 1);
+}
+
+#[tokio::test]
+async fn guards_are_checked_without_the_update_loop() {
+    let mock = MockTransport::new();
+    mock.answer("getChatAdministrators", json!([
+        {"user": {"id": 1, "first_name": "Admin"}, "status": "administrator"}
+    ]));
+
+    let mut bot = Bot::new("TOKEN").transport(mock.clone());
+    let mut bans = bot.new_cmd("/ban").boxed();
+    bot.guard("/ban", Guard::admins().silent());
+
+    assert!(bot.dispatch(text(1, -5, 2, "/ban")).await.is_none());
+    assert!(bot.dispatch(text(2, -5, 1, "/ban")).await.is_none());
+
+    let (_, msg) = tokio::time::timeout(Duration::from_secs(5), bans.next()).await.unwrap().unwrap().unwrap();
+    assert_eq!(msg.from.unwrap().id, 1);
+    assert!(bans.next().now_or_never().is_none());
+}
+
+/// Answers with the mock after a delay, so that requests overlap
+struct Slow(MockTransport);
+
+impl Transport for Slow {
+    fn fetch_json(&self, url: String, func: &'static str, msg: String) -> TransportFuture {
+        let answer = self.0.fetch_json(url, func, msg);
+
+        async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            answer.await
+        }.boxed()
+    }
+
+    fn fetch_formdata(&self, url: String, func: &'static str, msg: &Value, files: Vec<File>) -> TransportFuture {
+        self.0.fetch_formdata(url, func, msg, files)
+    }
+
+    fn download(&self, url: String) -> TransportStream {
+        self.0.download(url)
+    }
+}
+
+#[tokio::test]
+async fn concurrent_checks_share_their_requests() {
+    let mock = MockTransport::new();
+    mock.answer("getChatAdministrators", json!([
+        {"user": {"id": 1, "first_name": "Admin"}, "status": "administrator"}
+    ]));
+    mock.answer("getChatMember", json!({"user": {"id": 2, "first_name": "Test"}, "status": "member"}));
+
+    let bot = Bot::new("TOKEN").transport(Slow(mock.clone()));
+    let cache = bot.member_cache();
+
+    let admins = future::join_all((1..6).map(|user| cache.status(&bot.request, -5, user, true))).await;
+    assert_eq!(admins[0].as_ref().unwrap().as_deref(), Some("administrator"));
+    assert!(admins[1..].iter().all(|x| x.as_ref().unwrap().is_none()));
+    assert_eq!(mock.requests_to("getChatAdministrators").len(), 1);
+
+    let members = future::join_all((0..5).map(|_| cache.status(&bot.request, -5, 2, false))).await;
+    assert!(members.iter().all(|x| x.as_ref().unwrap().as_deref() == Some("member")));
+    assert_eq!(mock.requests_to("getChatMember").len(), 1);
+
+    // other chats are requested on their own
+    cache.status(&bot.request, -6, 1, true).await.unwrap();
+    assert_eq!(mock.requests_to("getChatAdministrators").len(), 2);
+}
+
+#[tokio::test]
+async fn failed_requests_reach_every_waiter() {
+    let mock = MockTransport::new();
+    mock.answer_raw("getChatAdministrators", error(400, json!({})));
+
+    let bot = Bot::new("TOKEN").transport(Slow(mock.clone()));
+    let cache = bot.member_cache();
+
+    let admins = future::join_all((1..4).map(|user| cache.status(&bot.request, -5, user, true))).await;
+    assert!(admins.iter().all(|x| x.is_err()));
+    assert_eq!(mock.requests_to("getChatAdministrators").len(), 1);
+
+    // the failure isn't cached
+    assert!(cache.status(&bot.request, -5, 1, true).await.is_err());
+    assert_eq!(mock.requests_to("getChatAdministrators").len(), 2);
+}
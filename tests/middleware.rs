@@ -0,0 +1,126 @@
+mod common;
+
+use telebot::Bot;
+use telebot::error::ErrorKind;
+use telebot::functions::*;
+use telebot::middleware::{Flow, Middleware, RequestContext, UpdateContext};
+use telebot::transport::MockTransport;
+
+use std::sync::{Arc, Mutex};
+
+use failure::Error;
+use futures::StreamExt;
+
+use common::{kind, message, received, text};
+
+/// Writes every hook it sees to a shared log
+struct Recorder {
+    name: &'static str,
+    log: Arc<Mutex<Vec<String>>>,
+}
+
+impl Middleware for Recorder {
+    fn on_update(&self, _ctx: &mut UpdateContext) -> Flow {
+        self.log.lock().unwrap().push(format!("update {}", self.name));
+        Flow::Continue
+    }
+
+    fn on_request(&self, ctx: &mut RequestContext) -> Flow {
+        self.log.lock().unwrap().push(format!("request {} {}", self.name, ctx.function));
+        Flow::Continue
+    }
+
+    fn on_response(&self, function: &'static str, _result: &Result<String, Error>) {
+        self.log.lock().unwrap().push(format!("response {} {}", self.name, function));
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Language(&'static str);
+
+#[tokio::test]
+async fn stopped_updates_reach_no_handler() {
+    let log = Arc::new(Mutex::new(Vec::new()));
+
+    let mut bot = Bot::new("TOKEN")
+        .middleware(|ctx: &mut UpdateContext| {
+            match ctx.update.message.as_ref().and_then(|x| x.from.as_ref()).map(|x| x.id) {
+                Some(1) => Flow::Stop,
+                _ => Flow::Continue,
+            }
+        })
+        .middleware(Recorder { name: "b", log: log.clone() });
+    let mut starts = bot.new_cmd("/start").boxed();
+
+    assert!(bot.dispatch(text(1, 7, 1, "/start")).await.is_none());
+    assert!(received(&mut starts).is_none());
+    assert!(log.lock().unwrap().is_empty());
+
+    bot.dispatch(text(2, 7, 2, "/start")).await;
+    assert!(received(&mut starts).is_some());
+    assert_eq!(*log.lock().unwrap(), vec!["update b"]);
+}
+
+#[tokio::test]
+async fn hooks_run_around_updates_and_requests() {
+    let mock = MockTransport::new();
+    mock.answer("sendMessage", message());
+
+    let log = Arc::new(Mutex::new(Vec::new()));
+
+    let mut bot = Bot::new("TOKEN")
+        .transport(mock.clone())
+        .middleware(Recorder { name: "a", log: log.clone() })
+        .middleware(Recorder { name: "b", log: log.clone() });
+    let mut starts = bot.new_cmd("/start").boxed();
+
+    bot.dispatch(text(1, 7, 7, "/start")).await;
+    let (handle, msg) = starts.next().await.unwrap().unwrap();
+    handle.message(msg.chat.id, "hi".into()).send().await.unwrap();
+
+    assert_eq!(*log.lock().unwrap(), vec![
+        "update a",
+        "update b",
+        "request a sendMessage",
+        "request b sendMessage",
+        "response b sendMessage",
+        "response a sendMessage",
+    ]);
+}
+
+#[tokio::test]
+async fn stopped_requests_fail() {
+    let mock = MockTransport::new();
+    mock.answer("sendMessage", message());
+
+    struct ReadOnly;
+
+    impl Middleware for ReadOnly {
+        fn on_request(&self, ctx: &mut RequestContext) -> Flow {
+            match ctx.function {
+                "sendMessage" => Flow::Stop,
+                _ => Flow::Continue,
+            }
+        }
+    }
+
+    let bot = Bot::new("TOKEN").transport(mock.clone()).middleware(ReadOnly);
+    let err = bot.request.message(7, "hi".into()).send().await.err().unwrap();
+
+    assert_eq!(kind(err), ErrorKind::Middleware);
+    assert!(mock.requests_to("sendMessage").is_empty());
+}
+
+#[tokio::test]
+async fn annotations_reach_the_handler() {
+    let mut bot = Bot::new("TOKEN").middleware(|ctx: &mut UpdateContext| {
+        ctx.annotations.insert(Language("de"));
+        Flow::Continue
+    });
+    let mut starts = bot.new_cmd("/start").boxed();
+
+    bot.dispatch(text(1, 7, 7, "/start")).await;
+    let (handle, _) = starts.next().await.unwrap().unwrap();
+
+    assert_eq!(handle.annotation::<Language>(), Some(&Language("de")));
+}
@@ -0,0 +1,73 @@
+mod common;
+
+use telebot::Bot;
+use telebot::error::TelegramError;
+use telebot::functions::*;
+use telebot::migration::ChatMigration;
+use telebot::objects::Update;
+use telebot::transport::MockTransport;
+
+use futures::{FutureExt, StreamExt};
+use serde_json::json;
+
+use common::{error, message};
+
+fn migrated(mock: &MockTransport) {
+    mock.answer_raw("sendMessage", error(400, json!({"migrate_to_chat_id": -1005})));
+    mock.answer("sendMessage", message());
+}
+
+#[tokio::test]
+async fn resends_to_the_migrated_chat() {
+    let mock = MockTransport::new();
+    migrated(&mock);
+
+    let mut bot = Bot::new("TOKEN").transport(mock.clone()).follow_migrations(true);
+    let mut migrations = bot.migrations().boxed();
+
+    bot.request.message(-5, "hi".into()).send().await.unwrap();
+
+    let requests = mock.requests_to("sendMessage");
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[0].body["chat_id"], -5);
+    assert_eq!(requests[1].body["chat_id"], -1005);
+
+    let (_, migration) = migrations.next().now_or_never().unwrap().unwrap().unwrap();
+    assert_eq!(migration, ChatMigration { from_chat_id: -5, to_chat_id: -1005 });
+}
+
+#[tokio::test]
+async fn returns_the_error_unless_migrations_are_followed() {
+    let mock = MockTransport::new();
+    migrated(&mock);
+
+    let bot = Bot::new("TOKEN").transport(mock.clone());
+    let err = bot.request.message(-5, "hi".into()).send().await.err().unwrap();
+
+    assert_eq!(TelegramError::find(&err).and_then(|x| x.migrate_to_chat_id()), Some(-1005));
+    assert_eq!(mock.requests_to("sendMessage").len(), 1);
+}
+
+#[tokio::test]
+async fn reports_each_migration_once() {
+    let mock = MockTransport::new();
+    migrated(&mock);
+
+    let mut bot = Bot::new("TOKEN").transport(mock.clone()).follow_migrations(true);
+    let mut migrations = bot.migrations().boxed();
+
+    bot.request.message(-5, "hi".into()).send().await.unwrap();
+
+    // the service messages of the old group and of the new supergroup
+    for (update_id, chat, key, other) in [(1, -5, "migrate_to_chat_id", -1005), (2, -1005, "migrate_from_chat_id", -5)] {
+        let update: Update = serde_json::from_value(json!({
+            "update_id": update_id,
+            "message": {"message_id": 1, "date": 0, "chat": {"id": chat, "type": "group"}, key: other}
+        })).unwrap();
+
+        bot.dispatch(update).await;
+    }
+
+    assert!(migrations.next().now_or_never().is_some());
+    assert!(migrations.next().now_or_never().is_none());
+}
@@ -0,0 +1,86 @@
+mod common;
+
+use telebot::Bot;
+use telebot::offset::{FileOffsetStore, MemoryOffsetStore, OffsetStore, UpdateGuard};
+use telebot::queue::Processing;
+use telebot::transport::MockTransport;
+
+use std::fs;
+
+use futures::{future, StreamExt};
+use serde_json::json;
+
+use common::{text_update, wait_for};
+
+#[test]
+fn guard_drops_updates_before_the_offset_and_repeated_ones() {
+    let guard = UpdateGuard::new(10);
+
+    assert!(!guard.check(9));
+    assert!(guard.check(10));
+    assert!(!guard.check(10));
+
+    // updates may arrive out of order
+    assert!(guard.check(12));
+    assert!(guard.check(11));
+    assert!(!guard.check(11));
+    assert_eq!(guard.offset(), 13);
+}
+
+#[test]
+fn guard_forgets_old_updates_behind_a_floor() {
+    let guard = UpdateGuard::new(0);
+
+    for id in 0..1100 {
+        assert!(guard.check(id));
+    }
+
+    // the oldest ids left the window, they are dropped because of the raised floor
+    assert!(!guard.check(0));
+    assert!(!guard.check(75));
+    assert!(!guard.check(1099));
+    assert_eq!(guard.offset(), 1100);
+}
+
+#[test]
+fn file_store_replaces_the_offset() {
+    let dir = std::env::temp_dir().join(format!("telebot-offset-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    let store = FileOffsetStore::new(dir.join("offset"));
+    assert_eq!(store.load().unwrap(), None);
+
+    store.save(41).unwrap();
+    store.save(42).unwrap();
+    assert_eq!(store.load().unwrap(), Some(42));
+
+    // the temporary file was renamed over the offset
+    let files = fs::read_dir(&dir).unwrap().map(|x| x.unwrap().file_name()).collect::<Vec<_>>();
+    assert_eq!(files, vec!["offset"]);
+
+    fs::write(dir.join("offset"), "garbage").unwrap();
+    assert!(store.load().is_err());
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn held_back_updates_are_not_confirmed() {
+    let mock = MockTransport::new();
+    mock.answer("getUpdates", json!([text_update(1, 7, 7, "/start"), text_update(2, 7, 7, "/start")]));
+
+    let store = MemoryOffsetStore::new();
+    let mut bot = Bot::new("TOKEN")
+        .transport(mock.clone())
+        .update_interval(10)
+        .processing(Processing::SequentialPerChat)
+        .offset_store(store.clone());
+
+    // the first command is never taken from the stream, so the second one stays held back
+    let _start = bot.new_cmd("/start");
+    tokio::spawn(bot.get_stream(None).for_each(|_| future::ready(())));
+
+    let requests = wait_for(&mock, "getUpdates", 3).await;
+    assert!(requests[1..].iter().all(|x| x.body["offset"] == 2));
+    assert_eq!(store.load().unwrap(), Some(2));
+}
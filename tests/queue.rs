@@ -0,0 +1,30 @@
+mod common;
+
+use telebot::Bot;
+
+use std::time::Duration;
+
+use futures::StreamExt;
+use tokio::time::timeout;
+
+use common::{received, text};
+
+#[tokio::test]
+async fn a_full_queue_holds_back_only_its_handler() {
+    let mut bot = Bot::new("TOKEN").handler_capacity(1);
+    let mut a = bot.new_cmd("/a").boxed();
+    let mut b = bot.new_cmd("/b").boxed();
+
+    bot.dispatch(text(1, 7, 7, "/a")).await;
+
+    // the queue of /a is full, but /b still reaches its handler
+    bot.dispatch(text(2, 8, 8, "/b")).await;
+    assert_eq!(received(&mut b).unwrap().chat.id, 8);
+
+    // another /a waits until its handler takes the first one
+    assert!(timeout(Duration::from_millis(50), bot.dispatch(text(3, 9, 9, "/a"))).await.is_err());
+
+    assert_eq!(received(&mut a).unwrap().chat.id, 7);
+    bot.dispatch(text(4, 9, 9, "/a")).await;
+    assert_eq!(received(&mut a).unwrap().chat.id, 9);
+}
@@ -0,0 +1,32 @@
+use telebot::ratelimit::{Limit, RateLimiter};
+
+use std::time::{Duration, Instant};
+
+use serde_json::json;
+
+#[test]
+fn only_messages_count_against_the_limits() {
+    for func in &["sendMessage", "sendPhoto", "forwardMessage", "copyMessage", "editMessageText"] {
+        assert!(RateLimiter::limits(func), "{}", func);
+    }
+
+    for func in &["getChat", "getChatMember", "getChatAdministrators", "sendChatAction", "deleteMessage"] {
+        assert!(!RateLimiter::limits(func), "{}", func);
+    }
+}
+
+#[tokio::test]
+async fn lookups_do_not_delay_messages() {
+    let limiter = RateLimiter::new().private_chat(Limit::new(1, Duration::from_millis(300)));
+    let msg = json!({"chat_id": 7});
+    let start = Instant::now();
+
+    limiter.wait("getChatMember", &msg).await;
+    limiter.wait("getChatAdministrators", &msg).await;
+    limiter.wait("sendMessage", &msg).await;
+    assert!(start.elapsed() < Duration::from_millis(100));
+
+    // the second message to the same chat waits for the next slot
+    limiter.wait("sendMessage", &msg).await;
+    assert!(start.elapsed() >= Duration::from_millis(250));
+}
@@ -0,0 +1,130 @@
+mod common;
+
+use telebot::Bot;
+use telebot::error::{ErrorKind, TelegramError};
+use telebot::file::File;
+use telebot::functions::*;
+use telebot::retry::RetryPolicy;
+use telebot::transport::{MockTransport, Transport, TransportFuture, TransportStream};
+
+use std::{sync::atomic::{AtomicUsize, Ordering}, time::Duration};
+
+use failure::{Context, Error};
+use futures::{future, FutureExt};
+use serde_json::{json, Value};
+
+use common::{error, message};
+
+fn bot(transport: MockTransport, max_attempts: u32) -> Bot {
+    let policy = RetryPolicy::new(max_attempts).network_delay(Duration::from_millis(1));
+
+    Bot::new("TOKEN").transport(transport).retry_policy(policy)
+}
+
+#[tokio::test]
+async fn retries_after_flood_control() {
+    let mock = MockTransport::new();
+    mock.answer_raw("sendMessage", error(429, json!({"retry_after": 0})));
+    mock.answer("sendMessage", message());
+
+    let bot = bot(mock.clone(), 3);
+    bot.request.message(7, "hi".into()).send().await.unwrap();
+
+    assert_eq!(mock.requests_to("sendMessage").len(), 2);
+}
+
+#[tokio::test]
+async fn retries_server_errors() {
+    let mock = MockTransport::new();
+    mock.answer_raw("sendMessage", error(502, json!({})));
+    mock.answer_raw("sendMessage", error(500, json!({})));
+    mock.answer("sendMessage", message());
+
+    let bot = bot(mock.clone(), 3);
+    bot.request.message(7, "hi".into()).send().await.unwrap();
+
+    assert_eq!(mock.requests_to("sendMessage").len(), 3);
+}
+
+#[tokio::test]
+async fn forwards_client_errors_at_once() {
+    let mock = MockTransport::new();
+    mock.answer_raw("sendMessage", error(400, json!({})));
+
+    let bot = bot(mock.clone(), 3);
+    let err = bot.request.message(7, "hi".into()).send().await.err().unwrap();
+
+    assert_eq!(TelegramError::find(&err).and_then(|x| x.error_code()), Some(400));
+    assert_eq!(mock.requests_to("sendMessage").len(), 1);
+}
+
+#[tokio::test]
+async fn stops_after_max_attempts() {
+    let mock = MockTransport::new();
+    mock.answer_raw("sendMessage", error(429, json!({"retry_after": 0})));
+
+    let bot = bot(mock.clone(), 2);
+    let err = bot.request.message(7, "hi".into()).send().await.err().unwrap();
+
+    assert_eq!(TelegramError::find(&err).and_then(|x| x.error_code()), Some(429));
+    assert_eq!(mock.requests_to("sendMessage").len(), 2);
+}
+
+#[tokio::test]
+async fn replays_uploaded_files() {
+    let mock = MockTransport::new();
+    mock.answer_raw("sendDocument", error(429, json!({"retry_after": 0})));
+    mock.answer("sendDocument", message());
+
+    let bot = bot(mock.clone(), 3);
+    bot.request.document(7).file(("notes.txt", &b"content"[..])).send().await.unwrap();
+
+    let requests = mock.requests_to("sendDocument");
+    assert_eq!(requests.len(), 2);
+    assert!(requests.iter().all(|x| x.files == vec!["attach://notes.txt".to_string()]));
+}
+
+/// Fails the first requests with a network error and answers the others with the mock
+struct Unreachable {
+    failures: AtomicUsize,
+    mock: MockTransport,
+}
+
+impl Transport for Unreachable {
+    fn fetch_json(&self, url: String, func: &'static str, msg: String) -> TransportFuture {
+        if self.failures.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |x| x.checked_sub(1)).is_ok() {
+            return future::err(Error::from(Context::new(ErrorKind::Hyper))).boxed();
+        }
+
+        self.mock.fetch_json(url, func, msg)
+    }
+
+    fn fetch_formdata(&self, url: String, func: &'static str, msg: &Value, files: Vec<File>) -> TransportFuture {
+        self.mock.fetch_formdata(url, func, msg, files)
+    }
+
+    fn download(&self, url: String) -> TransportStream {
+        self.mock.download(url)
+    }
+}
+
+#[tokio::test]
+async fn retries_network_errors() {
+    let mock = MockTransport::new();
+    mock.answer("sendMessage", message());
+
+    let transport = Unreachable { failures: AtomicUsize::new(1), mock: mock.clone() };
+    let policy = RetryPolicy::new(3).network_delay(Duration::from_millis(1));
+    let bot = Bot::new("TOKEN").transport(transport).retry_policy(policy);
+
+    bot.request.message(7, "hi".into()).send().await.unwrap();
+    assert_eq!(mock.requests_to("sendMessage").len(), 1);
+
+    // without network retries the error is returned
+    let transport = Unreachable { failures: AtomicUsize::new(1), mock: mock.clone() };
+    let bot = Bot::new("TOKEN")
+        .transport(transport)
+        .retry_policy(RetryPolicy::new(3).network_errors(false));
+
+    assert!(bot.request.message(7, "hi".into()).send().await.is_err());
+}
@@ -0,0 +1,96 @@
+mod common;
+
+use telebot::Bot;
+use telebot::filter::MessageFilter;
+use telebot::router::Route;
+
+use futures::StreamExt;
+
+use common::{callback, received, text};
+
+#[tokio::test]
+async fn regex_routes_match_text() {
+    let mut bot = Bot::new("TOKEN");
+    let mut greetings = bot.route(Route::regex(r"(?i)^(hi|hello)\b").unwrap()).boxed();
+
+    assert!(bot.dispatch(text(1, 7, 7, "Hello there")).await.is_none());
+    assert_eq!(received(&mut greetings).unwrap().text.unwrap(), "Hello there");
+
+    // messages without a matching route are returned
+    assert!(bot.dispatch(text(2, 7, 7, "bye")).await.is_some());
+    assert!(received(&mut greetings).is_none());
+}
+
+#[tokio::test]
+async fn routes_are_checked_by_priority_then_in_order() {
+    let mut bot = Bot::new("TOKEN");
+    let mut first = bot.route(Route::regex("^a").unwrap()).boxed();
+    let mut second = bot.route(Route::predicate(|msg| msg.text.is_some())).boxed();
+    let mut important = bot.route(Route::predicate(|msg| msg.chat.id < 0).priority(10)).boxed();
+
+    bot.dispatch(text(1, -5, 7, "apple")).await;
+    assert!(received(&mut important).is_some());
+
+    bot.dispatch(text(2, 7, 7, "apple")).await;
+    assert!(received(&mut first).is_some());
+
+    bot.dispatch(text(3, 7, 7, "banana")).await;
+    assert!(received(&mut second).is_some());
+
+    assert!(received(&mut first).is_none());
+    assert!(received(&mut second).is_none());
+    assert!(received(&mut important).is_none());
+}
+
+#[tokio::test]
+async fn commands_are_preferred_to_routes() {
+    let mut bot = Bot::new("TOKEN");
+    let mut routed = bot.route(Route::regex("^/").unwrap().priority(100)).boxed();
+    let mut starts = bot.new_cmd("/start").boxed();
+
+    bot.dispatch(text(1, 7, 7, "/start")).await;
+    assert!(received(&mut starts).is_some());
+    assert!(received(&mut routed).is_none());
+
+    // commands without a handler may still be routed
+    bot.dispatch(text(2, 7, 7, "/stop")).await;
+    assert_eq!(received(&mut routed).unwrap().text.unwrap(), "/stop");
+}
+
+#[tokio::test]
+async fn the_longest_callback_prefix_wins() {
+    let mut bot = Bot::new("TOKEN");
+    let mut short = bot.callback_prefix("vote").boxed();
+    let mut long = bot.callback_prefix("vote:up").boxed();
+    let mut others = bot.callback().boxed();
+
+    bot.dispatch(callback(1, 7, 7, "vote:up:3")).await;
+    assert_eq!(received(&mut long).unwrap().data.unwrap(), "vote:up:3");
+
+    bot.dispatch(callback(2, 7, 7, "vote:down:3")).await;
+    assert_eq!(received(&mut short).unwrap().data.unwrap(), "vote:down:3");
+
+    bot.dispatch(callback(3, 7, 7, "page:2")).await;
+    assert_eq!(received(&mut others).unwrap().data.unwrap(), "page:2");
+
+    assert!(received(&mut short).is_none());
+    assert!(received(&mut long).is_none());
+}
+
+#[tokio::test]
+async fn unrouted_messages_fall_through() {
+    let mut bot = Bot::new("TOKEN");
+    let mut greetings = bot.route(Route::regex("^hi").unwrap()).boxed();
+    let mut unknown = bot.unknown_cmd().boxed();
+    let mut messages = bot.messages(MessageFilter::new()).boxed();
+
+    bot.dispatch(text(1, 7, 7, "/nope")).await;
+    assert_eq!(received(&mut unknown).unwrap().text.unwrap(), "/nope");
+
+    bot.dispatch(text(2, 7, 7, "hello")).await;
+    assert_eq!(received(&mut messages).unwrap().text.unwrap(), "hello");
+
+    assert!(received(&mut greetings).is_none());
+    assert!(received(&mut unknown).is_none());
+    assert!(received(&mut messages).is_none());
+}
@@ -0,0 +1,49 @@
+use telebot::session::{FileSessionStore, MemorySessionStore, Session, SessionKey, SessionStore};
+
+use std::{thread, time::Duration};
+
+#[tokio::test]
+async fn dropped_sessions_are_saved_in_the_background() {
+    let store = MemorySessionStore::new();
+
+    let session = Session::load(SessionKey::User(3), store.clone()).unwrap();
+    session.set("name", "bee").unwrap();
+    drop(session);
+
+    for _ in 0..100 {
+        if store.load(SessionKey::User(3)).unwrap().is_some() {
+            break;
+        }
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    let session = Session::load(SessionKey::User(3), store).unwrap();
+    assert_eq!(session.get::<String>("name").unwrap(), Some("bee".into()));
+}
+
+#[test]
+fn concurrent_file_saves_do_not_collide() {
+    let dir = std::env::temp_dir().join(format!("telebot-sessions-{}", std::process::id()));
+    let store = FileSessionStore::new(&dir);
+
+    let threads = (0..8).map(|i| {
+        let store = store.clone();
+
+        thread::spawn(move || {
+            let mut values = serde_json::Map::new();
+            values.insert("count".into(), i.into());
+
+            for _ in 0..20 {
+                store.save(SessionKey::Chat(5), &values).unwrap();
+            }
+        })
+    }).collect::<Vec<_>>();
+
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    assert!(store.load(SessionKey::Chat(5)).unwrap().is_some());
+    std::fs::remove_dir_all(dir).unwrap();
+}
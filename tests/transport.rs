@@ -0,0 +1,71 @@
+use telebot::Bot;
+use telebot::file::File;
+use telebot::functions::*;
+use telebot::transport::{HyperTransport, MockTransport, Transport};
+
+use std::{io::Cursor, net::TcpListener, sync::{Arc, Mutex}, time::Duration};
+
+use futures::channel::oneshot;
+use hyper::{Body, Request, Response, Server, header::CONTENT_LENGTH, service::{make_service_fn, service_fn}};
+use serde_json::json;
+
+#[tokio::test]
+async fn timeout_keeps_a_custom_transport() {
+    let mock = MockTransport::new();
+    mock.answer("getMe", json!({"id": 1, "first_name": "Bot", "username": "test_bot"}));
+
+    let bot = Bot::new("TOKEN").transport(mock.clone()).timeout(10);
+    let (_, me) = bot.request.get_me().send().await.unwrap();
+
+    assert_eq!(me.username, Some("test_bot".into()));
+    assert_eq!(mock.requests_to("getMe").len(), 1);
+}
+
+#[tokio::test]
+async fn formdata_escapes_names_and_streams_files() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (sender, receiver) = oneshot::channel();
+    let sender = Arc::new(Mutex::new(Some(sender)));
+
+    let service = make_service_fn(move |_| {
+        let sender = sender.clone();
+
+        async move {
+            Ok::<_, hyper::Error>(service_fn(move |req: Request<Body>| {
+                let sender = sender.clone();
+
+                async move {
+                    let len = req.headers()[CONTENT_LENGTH].to_str().unwrap().parse::<usize>().unwrap();
+                    let body = hyper::body::to_bytes(req.into_body()).await?;
+
+                    if let Some(sender) = sender.lock().unwrap().take() {
+                        let _ = sender.send((len, body.to_vec()));
+                    }
+
+                    Ok::<_, hyper::Error>(Response::new(Body::from(r#"{"ok": true, "result": true}"#)))
+                }
+            }))
+        }
+    });
+
+    tokio::spawn(Server::from_tcp(listener).unwrap().serve(service));
+
+    let files = vec![
+        File::Memory { name: "evil\"\r\nX-Injected: 1".into(), source: Box::new(Cursor::new(b"memory".to_vec())) },
+        File::Disk { path: "examples/bee.jpg".into() },
+    ];
+
+    let transport = HyperTransport::new(Duration::from_secs(1));
+    let answer = transport.fetch_formdata(format!("http://{}/", addr), "sendPhoto", &json!({"chat_id": 7}), files).await.unwrap();
+    assert_eq!(answer, r#"{"ok": true, "result": true}"#);
+
+    let (len, body) = receiver.await.unwrap();
+    let text = String::from_utf8_lossy(&body);
+    assert_eq!(len, body.len());
+    assert!(text.contains("name=\"evil%22%0D%0AX-Injected: 1\""));
+    assert!(!text.contains("\r\nX-Injected"));
+
+    let bee = std::fs::read("examples/bee.jpg").unwrap();
+    assert!(body.windows(bee.len()).any(|x| x == &bee[..]));
+}
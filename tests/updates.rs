@@ -0,0 +1,81 @@
+mod common;
+
+use telebot::Bot;
+use telebot::functions::*;
+use telebot::transport::MockTransport;
+
+use serde_json::{json, Value};
+
+use common::wait_for;
+
+fn mock() -> MockTransport {
+    let mock = MockTransport::new();
+    mock.answer("getMe", json!({"id": 1, "is_bot": true, "first_name": "Bee", "username": "bee_bot"}));
+    mock.answer("getUpdates", json!([]));
+    mock.answer("setWebhook", json!(true));
+
+    mock
+}
+
+/// Runs the bot and returns the update types of its first getUpdates request
+async fn polled_updates(bot: Bot, mock: &MockTransport) -> Value {
+    tokio::spawn(bot.into_future());
+
+    wait_for(mock, "getUpdates", 1).await[0].body["allowed_updates"].clone()
+}
+
+#[tokio::test]
+async fn commands_only_request_messages() {
+    let mock = mock();
+    let mut bot = Bot::new("TOKEN").transport(mock.clone()).sync_commands(false);
+    let _start = bot.new_cmd("/start");
+
+    assert_eq!(polled_updates(bot, &mock).await, json!(["message"]));
+}
+
+#[tokio::test]
+async fn callback_and_inline_handlers_request_their_updates() {
+    let mock = mock();
+    let mut bot = Bot::new("TOKEN").transport(mock.clone()).sync_commands(false);
+    let _start = bot.new_cmd("/start");
+    let _callback = bot.callback();
+    let _inline = bot.inline();
+
+    assert_eq!(polled_updates(bot, &mock).await, json!(["message", "callback_query", "inline_query"]));
+}
+
+#[tokio::test]
+async fn explicit_updates_replace_the_registered_ones() {
+    let mock = mock();
+    let mut bot = Bot::new("TOKEN")
+        .transport(mock.clone())
+        .sync_commands(false)
+        .allowed_updates(vec!["edited_message".into()]);
+    let _start = bot.new_cmd("/start");
+
+    assert_eq!(polled_updates(bot, &mock).await, json!(["edited_message"]));
+}
+
+#[tokio::test]
+async fn the_webhook_is_set_with_the_registered_updates() {
+    let mock = mock();
+    let mut bot = Bot::new("TOKEN").transport(mock.clone());
+    let _start = bot.new_cmd("/start");
+
+    bot.request.set_webhook("https://example.com/hook".into())
+        .allowed_updates(bot.registered_updates())
+        .send().await.unwrap();
+
+    let mut bot = Bot::new("TOKEN").transport(mock.clone());
+    let _start = bot.new_cmd("/start");
+    let _callback = bot.callback();
+    let _inline = bot.inline();
+
+    bot.request.set_webhook("https://example.com/hook".into())
+        .allowed_updates(bot.registered_updates())
+        .send().await.unwrap();
+
+    let requests = mock.requests_to("setWebhook");
+    assert_eq!(requests[0].body["allowed_updates"], json!(["message"]));
+    assert_eq!(requests[1].body["allowed_updates"], json!(["message", "callback_query", "inline_query"]));
+}
@@ -0,0 +1,118 @@
+use telebot::Bot;
+use telebot::transport::MockTransport;
+use telebot::webhook::{Webhook, SECRET_TOKEN_HEADER};
+
+use std::{net::{SocketAddr, TcpListener}, time::Duration};
+
+use futures::{future, FutureExt, StreamExt};
+use hyper::{Body, Client, Method, Request, StatusCode};
+
+const UPDATE: &str = r#"{
+    "update_id": 42,
+    "message": {
+        "message_id": 7,
+        "date": 0,
+        "chat": {"id": 1, "type": "private"},
+        "from": {"id": 1, "first_name": "Test"},
+        "text": "hello"
+    }
+}"#;
+
+const COMMAND: &str = r#"{
+    "update_id": 43,
+    "message": {
+        "message_id": 8,
+        "date": 0,
+        "chat": {"id": 1, "type": "private"},
+        "from": {"id": 1, "first_name": "Test"},
+        "text": "/start",
+        "entities": [{"type": "bot_command", "offset": 0, "length": 6}]
+    }
+}"#;
+
+fn free_addr() -> SocketAddr {
+    TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap()
+}
+
+async fn post(addr: SocketAddr, path: &str, token: Option<&str>, body: &'static str) -> StatusCode {
+    let client = Client::new();
+
+    // the listener is bound by the spawned task, retry until it accepts connections
+    for _ in 0..50 {
+        let mut req = Request::builder()
+            .method(Method::POST)
+            .uri(format!("http://{}{}", addr, path))
+            .header("Content-Type", "application/json");
+
+        if let Some(token) = token {
+            req = req.header(SECRET_TOKEN_HEADER, token);
+        }
+
+        match client.request(req.body(Body::from(body)).unwrap()).await {
+            Ok(res) => return res.status(),
+            Err(_) => tokio::time::sleep(Duration::from_millis(20)).await,
+        }
+    }
+
+    panic!("the webhook listener did not accept connections");
+}
+
+#[tokio::test]
+async fn webhook_accepts_fixture_updates() {
+    let addr = free_addr();
+    let mut updates = Webhook::new(addr, "/telegram").secret_token("secret").listen().boxed();
+
+    let (sender, mut receiver) = futures::channel::mpsc::unbounded();
+    tokio::spawn(async move {
+        while let Some(update) = updates.next().await {
+            let _ = sender.unbounded_send(update);
+        }
+    });
+
+    assert_eq!(post(addr, "/telegram", Some("secret"), UPDATE).await, StatusCode::OK);
+
+    let update = receiver.next().await.unwrap().unwrap();
+    assert_eq!(update.update_id, 42);
+    assert_eq!(update.message.unwrap().text, Some("hello".into()));
+}
+
+#[tokio::test]
+async fn webhook_rejects_wrong_path_and_token() {
+    let addr = free_addr();
+    let mut updates = Webhook::new(addr, "/telegram").secret_token("secret").listen().boxed();
+
+    let (sender, mut receiver) = futures::channel::mpsc::unbounded();
+    tokio::spawn(async move {
+        while let Some(update) = updates.next().await {
+            let _ = sender.unbounded_send(update);
+        }
+    });
+
+    assert_eq!(post(addr, "/other", Some("secret"), UPDATE).await, StatusCode::NOT_FOUND);
+    assert_eq!(post(addr, "/telegram", Some("wrong"), UPDATE).await, StatusCode::UNAUTHORIZED);
+    assert_eq!(post(addr, "/telegram", None, UPDATE).await, StatusCode::UNAUTHORIZED);
+
+    // no rejected update reached the stream
+    assert!(receiver.next().now_or_never().is_none());
+}
+
+#[tokio::test]
+async fn webhook_updates_reach_the_command_handlers() {
+    let addr = free_addr();
+
+    let mut bot = Bot::new("TOKEN").transport(MockTransport::new());
+    let mut start = bot.new_cmd("/start").boxed();
+
+    let webhook = Webhook::new(addr, "/telegram").secret_token("secret");
+    tokio::spawn(bot.get_webhook_stream(None, webhook).for_each(|_| future::ready(())));
+
+    assert_eq!(post(addr, "/telegram", Some("wrong"), COMMAND).await, StatusCode::UNAUTHORIZED);
+    assert_eq!(post(addr, "/telegram", None, COMMAND).await, StatusCode::UNAUTHORIZED);
+    assert_eq!(post(addr, "/telegram", Some("secret"), COMMAND).await, StatusCode::OK);
+
+    let (_, msg) = tokio::time::timeout(Duration::from_secs(5), start.next()).await.unwrap().unwrap().unwrap();
+    assert_eq!(msg.message_id, 8);
+
+    // the rejected requests never reached the handler
+    assert!(start.next().now_or_never().is_none());
+}